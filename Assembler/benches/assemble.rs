@@ -0,0 +1,116 @@
+//! Throughput of the assembler on a generated program like the output of a
+//! VM translator, 1,000,000 lines long. Run with `cargo bench`; pass a line
+//! count to change the size.
+//!
+//! A program that long needs more words than the 32K ROM holds, so full
+//! assembly ends with that one error, but only after every pass has run over
+//! the whole input. The output formats are measured on the part that fits.
+
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+
+use hack_assembler::assemble_with;
+use hack_assembler::lexer::Lexer;
+use hack_assembler::output::Format;
+use hack_assembler::parser::{Parser, ROM_SIZE};
+
+const DEFAULT_LINES: usize = 1_000_000;
+/// Each measurement is the fastest of this many runs.
+const RUNS: usize = 5;
+
+fn main() {
+    // `cargo bench` passes `--bench`, and maybe a filter
+    let lines = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_LINES);
+    let code = generate(lines);
+    let lines = code.lines().count();
+    println!(
+        "{} lines, {:.1} MB of source, fastest of {} runs",
+        lines,
+        code.len() as f64 / 1e6,
+        RUNS
+    );
+
+    let time = bench(|| {
+        let lexer = Lexer::new(&code, "bench.asm").unwrap();
+        black_box(lexer.get_tokens().len());
+    });
+    report("lex", time, lines, code.len());
+
+    let time = bench(|| match assemble_with(&code, "bench.asm", Parser::new()) {
+        Ok(assembly) => {
+            black_box(assembly.words.len());
+        }
+        Err(errors) => {
+            let fits = errors.len() == 1 && errors[0].message.contains("words of ROM");
+            assert!(fits, "{:?}", errors);
+        }
+    });
+    report("full pipeline", time, lines, code.len());
+
+    let words: Vec<u16> = (0..ROM_SIZE)
+        .map(|address| address as u16 ^ 0x5a5a)
+        .collect();
+    for format in [Format::Hack, Format::BinaryBe] {
+        let time = bench(|| format.write(&words, &mut io::sink()).unwrap());
+        let name = format!("write {}", format.extension());
+        report(&name, time, words.len(), words.len() * 2);
+    }
+}
+
+/// The fastest of `RUNS` calls of `run`.
+fn bench(mut run: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Prints the throughput of handling `items` lines or words, `bytes` long,
+/// in `time`.
+fn report(name: &str, time: Duration, items: usize, bytes: usize) {
+    let seconds = time.as_secs_f64();
+    println!(
+        "{:16} {:8.1} ms {:8.2} M items/s {:8.1} MB/s",
+        name,
+        seconds * 1e3,
+        items as f64 / seconds / 1e6,
+        bytes as f64 / seconds / 1e6
+    );
+}
+
+/// Generates about `lines` lines of stack-machine code in the style of the
+/// VM translator: pushes, pops and arithmetic on static variables, with a
+/// label every 40 lines. Jumps only go to the first 2048 labels, which all
+/// lie within ROM however long the program is.
+fn generate(lines: usize) -> String {
+    let mut code = String::with_capacity(lines * 8);
+    let mut count = 0;
+    let mut block = 0;
+    while count < lines {
+        let snippet = match block % 8 {
+            0 => format!("(Main.loop.{})\n", block),
+            1 => format!("@{}\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n", block % 1000),
+            2 => "@SP\nAM=M-1\nD=M\nA=A-1\nM=D+M\n".to_string(),
+            3 => format!("@SP\nAM=M-1\nD=M\n@Main.static.{}\nM=D\n", block % 64),
+            4 => "@LCL\nD=M\n@2\nA=D+A\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n".to_string(),
+            5 => format!(
+                "@SP\nAM=M-1\nD=M\n@Main.loop.{}\nD;JNE // loop\n",
+                (block - 5) % 2048
+            ),
+            6 => "@SP\nAM=M-1\nD=M\nA=A-1\nD=M-D\nM=-1\n".to_string(),
+            _ => "\n// next block\n".to_string(),
+        };
+        count += snippet.lines().count();
+        code.push_str(&snippet);
+        block += 1;
+    }
+    code
+}
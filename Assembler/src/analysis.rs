@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+
+use crate::error::{AssembleError, Severity};
+use crate::lexer::{is_symbol, is_symbol_char, split_comment, split_lines};
+use crate::lexer::{recover_tokens, Comp, Dest, Directive, Instruction, Jump, Lexer, Token};
+use crate::parser::Parser;
+use crate::symbols::{Symbol, SymbolKind};
+
+/// A position in a document. Both fields are 0-based, and `character`
+/// counts UTF-16 code units as in the Language Server Protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A range in a file, which may be another file than the analysed one when
+/// a symbol is defined in an included file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub range: Range,
+}
+
+/// A diagnostic for the analysed document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    /// The message followed by any notes, one per line.
+    pub message: String,
+}
+
+/// A place in the analysed document where a symbol is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Occurrence {
+    name: String,
+    line: usize,
+    /// Byte offsets of the name in the line.
+    start: usize,
+    end: usize,
+    /// Whether this is where the symbol is defined, such as `(LOOP)`.
+    definition: bool,
+}
+
+/// What an editor needs to know about one Hack assembly document: its
+/// diagnostics, the symbols it defines and where each is used. It is built
+/// by running the document through the assembler, so symbols defined in
+/// included files are known too.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    file: String,
+    lines: Vec<String>,
+    tokens: Vec<Token<'static>>,
+    symbols: HashMap<String, Symbol>,
+    /// The assembled words, if the document assembles.
+    words: Option<Vec<u16>>,
+    diagnostics: Vec<Diagnostic>,
+    occurrences: Vec<Occurrence>,
+    comp_mnemonics: Vec<String>,
+    dest_mnemonics: Vec<String>,
+    jump_mnemonics: Vec<String>,
+}
+
+impl Analysis {
+    /// Assembles `code` with `parser`, keeping whatever it learned even when
+    /// assembly fails. `file` names the document for diagnostics and is the
+    /// base for relative `.include` paths.
+    pub fn new(code: &str, file: &str, mut parser: Parser) -> Analysis {
+        let extended = parser.get_instruction_set().get_comps().iter();
+        let comp_mnemonics = sorted(
+            Comp::STANDARD
+                .iter()
+                .chain(extended.map(|(comp, _)| comp))
+                .map(|comp| comp.mnemonic()),
+        );
+        let dest_mnemonics = sorted(Dest::ALL.iter().map(|dest| dest.mnemonic()));
+        let jump_mnemonics = sorted(Jump::ALL.iter().map(|jump| jump.mnemonic()));
+
+        let mut errors = Vec::new();
+        let mut words = None;
+        let tokens = match Lexer::new(code, file) {
+            Ok(lexer) => {
+                let tokens = lexer.into_tokens();
+                let result = parser
+                    .first_pass(&tokens)
+                    .and_then(|_| parser.parse(&tokens));
+                match result {
+                    Ok(assembled) => words = Some(assembled),
+                    Err(found) => errors = found,
+                }
+                tokens
+            }
+            Err(found) => {
+                // Symbols still resolve in a document that is being edited
+                let tokens = recover_tokens(code, file, &found);
+                let _ = parser.first_pass(&tokens);
+                errors = found;
+                tokens
+            }
+        };
+        errors.extend(parser.get_warnings().iter().cloned());
+
+        let mut analysis = Analysis {
+            file: String::from(file),
+            lines: split_lines(code).map(String::from).collect(),
+            // The document outlives the text it was built from
+            tokens: tokens.into_iter().map(Token::into_owned).collect(),
+            symbols: parser.get_symbol_table().clone(),
+            words,
+            diagnostics: Vec::new(),
+            occurrences: Vec::new(),
+            comp_mnemonics,
+            dest_mnemonics,
+            jump_mnemonics,
+        };
+        analysis.diagnostics = errors
+            .iter()
+            .map(|error| analysis.diagnostic(error))
+            .collect();
+        analysis.occurrences = analysis.find_occurrences();
+        analysis
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The name of the symbol at `position`, if any.
+    pub fn symbol_at(&self, position: Position) -> Option<&str> {
+        self.occurrence_at(position)
+            .map(|occurrence| occurrence.name.as_str())
+    }
+
+    /// Where the symbol at `position` is defined.
+    pub fn definition(&self, position: Position) -> Option<Location> {
+        let name = self.symbol_at(position)?;
+        if let Some(occurrence) = self
+            .occurrences
+            .iter()
+            .find(|occurrence| occurrence.name == name && occurrence.definition)
+        {
+            return Some(self.location(occurrence));
+        }
+
+        // Defined in an included file
+        let token = self.tokens.iter().find(|token| {
+            token.expansion().is_none() && defined_name(token.get_token()) == Some(name)
+        })?;
+        let start = find_word(token.source(), name)?;
+        let line = token.line() - 1;
+        Some(Location {
+            file: String::from(token.file()),
+            range: Range {
+                start: Position {
+                    line,
+                    character: utf16_len(&token.source()[..start]),
+                },
+                end: Position {
+                    line,
+                    character: utf16_len(&token.source()[..start + name.len()]),
+                },
+            },
+        })
+    }
+
+    /// Every place in the document where the symbol at `position` is
+    /// written, including its definition if `include_definition` is set.
+    pub fn references(&self, position: Position, include_definition: bool) -> Vec<Location> {
+        let name = match self.symbol_at(position) {
+            Some(name) => name,
+            None => return Vec::new(),
+        };
+        self.occurrences
+            .iter()
+            .filter(|occurrence| occurrence.name == name)
+            .filter(|occurrence| include_definition || !occurrence.definition)
+            .map(|occurrence| self.location(occurrence))
+            .collect()
+    }
+
+    /// Markdown describing the symbol at `position` and the words the line
+    /// assembles to, with the range of the symbol if there is one.
+    pub fn hover(&self, position: Position) -> Option<(String, Option<Range>)> {
+        let mut parts = Vec::new();
+        let occurrence = self.occurrence_at(position);
+        if let Some(occurrence) = occurrence {
+            let symbol = self.symbols.get(&occurrence.name)?;
+            parts.push(describe(&occurrence.name, symbol));
+        }
+
+        let words = self.line_words(position.line + 1);
+        if !words.is_empty() {
+            let lines: Vec<String> = words
+                .iter()
+                .map(|(address, word)| format!("{:5}  {:016b}  {:04X}", address, word, word))
+                .collect();
+            parts.push(format!("```\n{}\n```", lines.join("\n")));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            let range = occurrence.map(|occurrence| self.range(occurrence));
+            Some((parts.join("\n\n"), range))
+        }
+    }
+
+    /// Completions for the text before `position`: symbols after `@`, jump
+    /// mnemonics after `;`, comp mnemonics after `=`, and comp and dest
+    /// mnemonics at the start of an instruction. Each is paired with its
+    /// kind, such as `label` or `comp`.
+    pub fn completions(&self, position: Position) -> Vec<(String, &'static str)> {
+        let line = match self.lines.get(position.line) {
+            Some(line) => line,
+            None => return Vec::new(),
+        };
+        let prefix = &line[..byte_offset(line, position.character)];
+        let (code, comment) = split_comment(prefix);
+        let code = code.trim_start();
+        if comment.is_some() || code.starts_with('(') || code.starts_with('.') {
+            return Vec::new();
+        }
+
+        if code.starts_with('@') {
+            let mut symbols: Vec<(String, &'static str)> = self
+                .symbols
+                .iter()
+                .filter(|(name, _)| !name.contains('$'))
+                .map(|(name, symbol)| (name.clone(), symbol.kind.name()))
+                .collect();
+            symbols.sort();
+            return symbols;
+        }
+        let named = |names: &[String], kind| {
+            names
+                .iter()
+                .map(|name| (name.clone(), kind))
+                .collect::<Vec<_>>()
+        };
+        if code.contains(';') {
+            named(&self.jump_mnemonics, "jump")
+        } else if code.contains('=') {
+            named(&self.comp_mnemonics, "comp")
+        } else {
+            let mut completions = named(&self.comp_mnemonics, "comp");
+            completions.extend(
+                self.dest_mnemonics
+                    .iter()
+                    .map(|dest| (format!("{}=", dest), "dest")),
+            );
+            completions
+        }
+    }
+
+    /// The edits that rename the label at `position` to `new_name`
+    /// throughout the document, or why it cannot be renamed.
+    pub fn rename(
+        &self,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Vec<(Range, String)>, String> {
+        let name = self
+            .symbol_at(position)
+            .ok_or_else(|| String::from("no symbol here"))?;
+        match self.symbols.get(name) {
+            Some(symbol) if symbol.kind == SymbolKind::Label => {}
+            _ => {
+                return Err(format!(
+                    "only labels can be renamed, and '{}' is not one",
+                    name
+                ))
+            }
+        }
+        if !self
+            .occurrences
+            .iter()
+            .any(|occurrence| occurrence.name == name && occurrence.definition)
+        {
+            return Err(format!("'{}' is defined in another file", name));
+        }
+        if !is_symbol(new_name) {
+            return Err(format!("'{}' is not a valid symbol name", new_name));
+        }
+        if self.symbols.contains_key(new_name) {
+            return Err(format!("'{}' is already defined", new_name));
+        }
+        Ok(self
+            .occurrences
+            .iter()
+            .filter(|occurrence| occurrence.name == name)
+            .map(|occurrence| (self.range(occurrence), String::from(new_name)))
+            .collect())
+    }
+
+    fn occurrence_at(&self, position: Position) -> Option<&Occurrence> {
+        let line = self.lines.get(position.line)?;
+        let offset = byte_offset(line, position.character);
+        self.occurrences.iter().find(|occurrence| {
+            occurrence.line == position.line
+                && occurrence.start <= offset
+                && offset <= occurrence.end
+        })
+    }
+
+    /// Scans the document for names in the symbol table, outside of
+    /// comments, C-instructions and `.include` paths. Inside a macro body
+    /// the labels local to the macro are skipped, and so are parameters.
+    fn find_occurrences(&self) -> Vec<Occurrence> {
+        let mut occurrences = Vec::new();
+        let mut local_labels: Option<Vec<String>> = None;
+        for (index, source) in self.lines.iter().enumerate() {
+            let code = split_comment(source).0;
+            let trimmed = code.trim_start();
+            let directive = trimmed.split_whitespace().next().unwrap_or("");
+            match directive {
+                ".macro" => {
+                    local_labels = Some(self.macro_labels(index + 1));
+                    continue;
+                }
+                ".endm" => {
+                    local_labels = None;
+                    continue;
+                }
+                ".include" => continue,
+                _ => {}
+            }
+            let is_c_instruction = !trimmed.starts_with(['@', '(', '.'])
+                && (trimmed.contains('=') || trimmed.contains(';') || !trimmed.contains(' '));
+            if trimmed.is_empty() || is_c_instruction {
+                continue;
+            }
+
+            let mut start = 0;
+            while start < code.len() {
+                let rest = &code[start..];
+                let c = rest.chars().next().unwrap();
+                if !is_symbol_char(c) {
+                    start += c.len_utf8();
+                    continue;
+                }
+                let len = rest.find(|c| !is_symbol_char(c)).unwrap_or(rest.len());
+                let word = &rest[..len];
+                let parameter = code[..start].ends_with('\\');
+                let local = local_labels
+                    .as_ref()
+                    .is_some_and(|labels| labels.iter().any(|label| label == word));
+                if !parameter && !local && self.symbols.contains_key(word) {
+                    let definition = match directive {
+                        _ if trimmed.starts_with('(') => true,
+                        ".equ" | ".define" | ".var" | ".data" | ".extern" => {
+                            trimmed.split_whitespace().nth(1) == Some(word)
+                        }
+                        _ => false,
+                    };
+                    occurrences.push(Occurrence {
+                        name: String::from(word),
+                        line: index,
+                        start,
+                        end: start + len,
+                        definition,
+                    });
+                }
+                start += len;
+            }
+        }
+        occurrences
+    }
+
+    /// The labels declared in the macro body starting at line `start`.
+    fn macro_labels(&self, start: usize) -> Vec<String> {
+        self.lines[start..]
+            .iter()
+            .map(|line| split_comment(line).0.trim())
+            .take_while(|code| !code.starts_with(".endm"))
+            .filter_map(|code| code.strip_prefix('(')?.strip_suffix(')'))
+            .map(|label| String::from(label.trim()))
+            .collect()
+    }
+
+    /// The ROM address and word of every instruction written on the
+    /// 1-based line `number`, including those expanded from a macro call there.
+    fn line_words(&self, number: usize) -> Vec<(usize, u16)> {
+        let words = match &self.words {
+            Some(words) => words,
+            None => return Vec::new(),
+        };
+        let mut found = Vec::new();
+        let mut address = 0;
+        for token in &self.tokens {
+            if !token.emits_word() {
+                continue;
+            }
+            let (file, line) = match token.expansion() {
+                Some(expansion) => {
+                    let mut outermost = expansion;
+                    while let Some(parent) = &outermost.parent {
+                        outermost = parent;
+                    }
+                    (&*outermost.file, outermost.line)
+                }
+                None => (token.file(), token.line()),
+            };
+            if file == self.file && line == number {
+                found.push((address, words[address]));
+            }
+            address += 1;
+        }
+        found
+    }
+
+    fn diagnostic(&self, error: &AssembleError) -> Diagnostic {
+        let mut message = error.message.clone();
+        for note in &error.notes {
+            message.push_str(&format!("\nnote: {}", note));
+        }
+        let range = if error.file == self.file && error.line > 0 {
+            let line = error.line - 1;
+            let source = self.lines.get(line).map_or("", String::as_str);
+            let start = source
+                .char_indices()
+                .nth(error.column.saturating_sub(1))
+                .map_or(source.len(), |(offset, _)| offset);
+            let end = source[start..]
+                .char_indices()
+                .nth(error.width)
+                .map_or(source.len(), |(offset, _)| start + offset);
+            Range {
+                start: Position {
+                    line,
+                    character: utf16_len(&source[..start]),
+                },
+                end: Position {
+                    line,
+                    character: utf16_len(&source[..end]),
+                },
+            }
+        } else {
+            // Problems in included files are shown at the top of the document
+            if error.line > 0 {
+                message = format!(
+                    "{}:{}:{}: {}",
+                    error.file, error.line, error.column, message
+                );
+            }
+            Range {
+                start: Position {
+                    line: 0,
+                    character: 0,
+                },
+                end: Position {
+                    line: 0,
+                    character: 0,
+                },
+            }
+        };
+        Diagnostic {
+            range,
+            severity: error.severity,
+            message,
+        }
+    }
+
+    fn range(&self, occurrence: &Occurrence) -> Range {
+        let line = &self.lines[occurrence.line];
+        Range {
+            start: Position {
+                line: occurrence.line,
+                character: utf16_len(&line[..occurrence.start]),
+            },
+            end: Position {
+                line: occurrence.line,
+                character: utf16_len(&line[..occurrence.end]),
+            },
+        }
+    }
+
+    fn location(&self, occurrence: &Occurrence) -> Location {
+        Location {
+            file: self.file.clone(),
+            range: self.range(occurrence),
+        }
+    }
+}
+
+/// The symbol `instruction` defines, if any.
+fn defined_name<'a>(instruction: &'a Instruction) -> Option<&'a str> {
+    match instruction {
+        Instruction::LInstruction(name) => Some(name),
+        Instruction::Directive(Directive::Constant { name, .. })
+        | Instruction::Directive(Directive::Var { name, .. })
+        | Instruction::Directive(Directive::Data { name, .. })
+        | Instruction::Directive(Directive::Extern(name)) => Some(name),
+        _ => None,
+    }
+}
+
+fn describe(name: &str, symbol: &Symbol) -> String {
+    let place = match symbol.kind {
+        SymbolKind::Label => "ROM address",
+        SymbolKind::Constant => "value",
+        _ => "RAM address",
+    };
+    let mut description = format!(
+        "{} `{}`: {} {} (0x{:04X})",
+        symbol.kind.name(),
+        name,
+        place,
+        symbol.address,
+        symbol.address
+    );
+    if symbol.size > 1 {
+        description.push_str(&format!(", {} words", symbol.size));
+    }
+    description
+}
+
+/// The byte offset in `line` of the start of whole word `name`.
+fn find_word(line: &str, name: &str) -> Option<usize> {
+    line.match_indices(name)
+        .map(|(offset, _)| offset)
+        .find(|offset| {
+            let before = line[..*offset].chars().next_back();
+            let after = line[offset + name.len()..].chars().next();
+            !before.is_some_and(is_symbol_char) && !after.is_some_and(is_symbol_char)
+        })
+}
+
+/// The mnemonics in alphabetical order, without `null`.
+fn sorted<'a>(mnemonics: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut mnemonics: Vec<_> = mnemonics
+        .filter(|mnemonic| *mnemonic != "null")
+        .map(String::from)
+        .collect();
+    mnemonics.sort_unstable();
+    mnemonics
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The byte offset in `line` of UTF-16 column `character`, clamped to the
+/// end of the line.
+fn byte_offset(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= character {
+            return offset;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
@@ -0,0 +1,76 @@
+use std::{env, fs, process};
+
+use hack_assembler::config::Config;
+use hack_assembler::disassembler::{read_hack, read_symbol_map, Disassembler};
+use hack_assembler::error::AssembleError;
+
+const USAGE: &str = "usage: hack_disassembler [--labels] [--symbols <file>] [--config <file>] \
+                     [-o <file.asm>] <file.hack>";
+
+fn main() {
+    let mut label_jumps = false;
+    let mut symbols_file = None;
+    let mut config_file = None;
+    let mut output = None;
+    let mut input = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--labels" => label_jumps = true,
+            "--symbols" => symbols_file = Some(args.next().unwrap_or_else(|| usage())),
+            "--config" => config_file = Some(args.next().unwrap_or_else(|| usage())),
+            "-o" => output = Some(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with('-') || input.is_some() => usage(),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.unwrap_or_else(|| usage());
+
+    let words = read_hack(&read(&input), &input).unwrap_or_else(|errors| fail(&errors));
+    let mut disassembler = Disassembler::new().label_jumps(label_jumps);
+    if let Some(symbols_file) = symbols_file {
+        let symbols = read_symbol_map(&read(&symbols_file), &symbols_file)
+            .unwrap_or_else(|errors| fail(&errors));
+        disassembler = disassembler.with_symbols(symbols);
+    }
+    if let Some(config_file) = config_file {
+        let config =
+            Config::parse(&read(&config_file), &config_file).unwrap_or_else(|errors| fail(&errors));
+        disassembler = disassembler.with_instruction_set(&config.instruction_set);
+    }
+
+    let disassembly = disassembler.disassemble(&words);
+    for address in &disassembly.illegal {
+        eprintln!(
+            "warning: illegal instruction {:016b} at address {}",
+            words[*address], address
+        );
+    }
+    match output {
+        Some(output) => fs::write(&output, disassembly.source).unwrap_or_else(|error| {
+            eprintln!("error: could not write {}: {}", output, error);
+            process::exit(1);
+        }),
+        None => print!("{}", disassembly.source),
+    }
+}
+
+fn read(file_name: &str) -> String {
+    fs::read_to_string(file_name).unwrap_or_else(|error| {
+        eprintln!("error: could not open {}: {}", file_name, error);
+        process::exit(1);
+    })
+}
+
+fn fail(errors: &[AssembleError]) -> ! {
+    for error in errors {
+        eprintln!("{}\n", error);
+    }
+    process::exit(1);
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
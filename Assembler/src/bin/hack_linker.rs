@@ -0,0 +1,111 @@
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::{env, fs, process};
+
+use hack_assembler::config::Config;
+use hack_assembler::error::AssembleError;
+use hack_assembler::linker::link;
+use hack_assembler::object::Object;
+use hack_assembler::output::Format;
+use hack_assembler::symbols::{write_symbols, SymbolFormat};
+
+const USAGE: &str = "usage: hack_linker [--config <file>] [--format <format>] [--symbols] [-o <file>] <file.hobj>...";
+
+fn main() {
+    let mut config = Config::default();
+    let mut format = Format::Hack;
+    let mut symbols = false;
+    let mut output = None;
+    let mut inputs = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                format = Format::from_name(&name).unwrap_or_else(|| {
+                    eprintln!(
+                        "error: unknown format '{}', expected one of: {}",
+                        name,
+                        Format::NAMES.join(", ")
+                    );
+                    usage()
+                });
+            }
+            "--config" => {
+                let file = args.next().unwrap_or_else(|| usage());
+                config = Config::parse(&read(&file), &file).unwrap_or_else(|errors| fail(&errors));
+            }
+            "--symbols" => symbols = true,
+            "-o" => output = Some(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with('-') => usage(),
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        usage();
+    }
+
+    let mut modules = Vec::new();
+    let mut errors = Vec::new();
+    for input in &inputs {
+        match Object::read(&read(input), input) {
+            Ok(object) => modules.push((input.clone(), object)),
+            Err(object_errors) => errors.extend(object_errors),
+        }
+    }
+    if !errors.is_empty() {
+        fail(&errors);
+    }
+
+    let linked = link(&modules, &config).unwrap_or_else(|errors| fail(&errors));
+    for warning in &linked.warnings {
+        eprintln!("{}\n", warning);
+    }
+
+    // The program is named after the first module, which holds the entry point
+    let output = output.unwrap_or_else(|| path(&inputs[0], format.extension()));
+    write(&output, |writer| format.write(&linked.words, writer));
+    if symbols {
+        write(&path(&output, "sym"), |writer| {
+            write_symbols(&linked.symbols, SymbolFormat::Text, writer)
+        });
+    }
+}
+
+fn path(file_name: &str, extension: &str) -> String {
+    Path::new(file_name)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn read(file_name: &str) -> String {
+    fs::read_to_string(file_name).unwrap_or_else(|error| {
+        eprintln!("error: could not open {}: {}", file_name, error);
+        process::exit(1);
+    })
+}
+
+fn write(file_name: &str, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+    let result = fs::File::create(file_name).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer).and_then(|_| writer.flush())
+    });
+    if let Err(error) = result {
+        eprintln!("error: could not write {}: {}", file_name, error);
+        process::exit(1);
+    }
+}
+
+fn fail(errors: &[AssembleError]) -> ! {
+    for error in errors {
+        eprintln!("{}\n", error);
+    }
+    process::exit(1);
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
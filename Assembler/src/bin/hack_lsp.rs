@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
+
+use hack_assembler::analysis::{Analysis, Location, Position, Range};
+use hack_assembler::config::Config;
+use hack_assembler::error::{AssembleError, Severity};
+use hack_assembler::json::Value;
+use hack_assembler::parser::Parser;
+
+const USAGE: &str = "usage: hack_lsp [--config <file>]";
+
+/// JSON-RPC error code for requests the server does not implement.
+const METHOD_NOT_FOUND: i32 = -32601;
+/// JSON-RPC error code for malformed request parameters.
+const INVALID_PARAMS: i32 = -32602;
+/// LSP error code for a request that is valid but cannot be carried out.
+const REQUEST_FAILED: i32 = -32803;
+
+fn main() {
+    let mut config = Config::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let file = args.next().unwrap_or_else(|| usage());
+                let code = fs::read_to_string(&file).unwrap_or_else(|error| {
+                    eprintln!("error: could not open {}: {}", file, error);
+                    process::exit(1);
+                });
+                config = Config::parse(&code, &file).unwrap_or_else(|errors| fail(&errors));
+            }
+            _ => usage(),
+        }
+    }
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut server = Server {
+        config,
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(error) => {
+                eprintln!("hack_lsp: {}", error);
+                continue;
+            }
+        };
+        if message.get("method").and_then(Value::as_str) == Some("exit") {
+            process::exit(if server.shutdown { 0 } else { 1 });
+        }
+        server.handle(&message);
+    }
+}
+
+/// The state of the server: the open documents, analysed on every change.
+struct Server {
+    config: Config,
+    documents: HashMap<String, Analysis>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn handle(&mut self, message: &Value) {
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let id = match message.get("id") {
+            Some(id) => id,
+            None => return self.notification(method, params),
+        };
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.with_document(params, |analysis, position| {
+                Ok(analysis
+                    .definition(position)
+                    .map_or(Value::Null, |location| location_json(&location)))
+            }),
+            "textDocument/references" => self.with_document(params, |analysis, position| {
+                let declaration = params
+                    .get("context")
+                    .and_then(|context| context.get("includeDeclaration"))
+                    == Some(&Value::Bool(true));
+                let locations = analysis.references(position, declaration);
+                Ok(Value::Array(locations.iter().map(location_json).collect()))
+            }),
+            "textDocument/hover" => self.with_document(params, |analysis, position| {
+                Ok(match analysis.hover(position) {
+                    Some((markdown, range)) => {
+                        let mut members = vec![(
+                            "contents",
+                            Value::object(vec![
+                                ("kind", Value::from("markdown")),
+                                ("value", Value::from(markdown)),
+                            ]),
+                        )];
+                        if let Some(range) = range {
+                            members.push(("range", range_json(range)));
+                        }
+                        Value::object(members)
+                    }
+                    None => Value::Null,
+                })
+            }),
+            "textDocument/completion" => self.with_document(params, |analysis, position| {
+                let items = analysis
+                    .completions(position)
+                    .into_iter()
+                    .map(|(label, kind)| {
+                        Value::object(vec![
+                            ("label", Value::from(label)),
+                            ("kind", Value::from(completion_kind(kind))),
+                            ("detail", Value::from(kind)),
+                        ])
+                    })
+                    .collect();
+                Ok(Value::Array(items))
+            }),
+            "textDocument/rename" => self.with_document(params, |analysis, position| {
+                let new_name = params
+                    .get("newName")
+                    .and_then(Value::as_str)
+                    .ok_or((INVALID_PARAMS, String::from("missing newName")))?;
+                let edits = analysis
+                    .rename(position, new_name)
+                    .map_err(|message| (REQUEST_FAILED, message))?;
+                let edits = edits
+                    .into_iter()
+                    .map(|(range, text)| {
+                        Value::object(vec![
+                            ("range", range_json(range)),
+                            ("newText", Value::from(text)),
+                        ])
+                    })
+                    .collect();
+                let uri = document_uri(params).unwrap_or("");
+                Ok(Value::object(vec![(
+                    "changes",
+                    Value::object(vec![(uri, Value::Array(edits))]),
+                )]))
+            }),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        };
+
+        let response = match result {
+            Ok(result) => Value::object(vec![
+                ("jsonrpc", Value::from("2.0")),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            Err((code, message)) => Value::object(vec![
+                ("jsonrpc", Value::from("2.0")),
+                ("id", id.clone()),
+                (
+                    "error",
+                    Value::object(vec![
+                        ("code", Value::Number(code as f64)),
+                        ("message", Value::from(message)),
+                    ]),
+                ),
+            ]),
+        };
+        send(&response);
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+        let uri = match document_uri(params) {
+            Some(uri) => String::from(uri),
+            None => return,
+        };
+        let text = match method {
+            "textDocument/didOpen" => params
+                .get("textDocument")
+                .and_then(|document| document.get("text"))
+                .and_then(Value::as_str),
+            // The server asks for full document sync, so the last change is the whole text
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Value::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text"))
+                .and_then(Value::as_str),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                publish_diagnostics(&uri, Vec::new());
+                return;
+            }
+            _ => return,
+        };
+        if let Some(text) = text {
+            let parser = Parser::new().with_config(self.config.clone());
+            let analysis = Analysis::new(text, &uri_to_path(&uri), parser);
+            let diagnostics = analysis
+                .diagnostics()
+                .iter()
+                .map(|diagnostic| {
+                    let severity = match diagnostic.severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    };
+                    Value::object(vec![
+                        ("range", range_json(diagnostic.range)),
+                        ("severity", Value::from(severity)),
+                        ("source", Value::from("hack")),
+                        ("message", Value::from(diagnostic.message.as_str())),
+                    ])
+                })
+                .collect();
+            publish_diagnostics(&uri, diagnostics);
+            self.documents.insert(uri, analysis);
+        }
+    }
+
+    /// Runs `request` against the open document and position named in `params`.
+    fn with_document(
+        &self,
+        params: &Value,
+        request: impl FnOnce(&Analysis, Position) -> Result<Value, (i32, String)>,
+    ) -> Result<Value, (i32, String)> {
+        let analysis = document_uri(params)
+            .and_then(|uri| self.documents.get(uri))
+            .ok_or((INVALID_PARAMS, String::from("unknown document")))?;
+        let position = params
+            .get("position")
+            .and_then(|position| {
+                Some(Position {
+                    line: position.get("line")?.as_usize()?,
+                    character: position.get("character")?.as_usize()?,
+                })
+            })
+            .ok_or((INVALID_PARAMS, String::from("missing position")))?;
+        request(analysis, position)
+    }
+}
+
+fn capabilities() -> Value {
+    Value::object(vec![
+        (
+            "capabilities",
+            Value::object(vec![
+                // Full document sync
+                ("textDocumentSync", Value::from(1)),
+                ("definitionProvider", Value::from(true)),
+                ("referencesProvider", Value::from(true)),
+                ("hoverProvider", Value::from(true)),
+                (
+                    "completionProvider",
+                    Value::object(vec![(
+                        "triggerCharacters",
+                        Value::Array(vec![Value::from("@"), Value::from("="), Value::from(";")]),
+                    )]),
+                ),
+                ("renameProvider", Value::from(true)),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Value::object(vec![
+                ("name", Value::from("hack_lsp")),
+                ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+/// The LSP `CompletionItemKind` for a kind returned by `Analysis::completions`.
+fn completion_kind(kind: &str) -> usize {
+    match kind {
+        "label" => 18,
+        "variable" => 6,
+        "constant" | "predefined" => 21,
+        _ => 14,
+    }
+}
+
+fn document_uri(params: &Value) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) {
+    send(&Value::object(vec![
+        ("jsonrpc", Value::from("2.0")),
+        ("method", Value::from("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Value::object(vec![
+                ("uri", Value::from(uri)),
+                ("diagnostics", Value::Array(diagnostics)),
+            ]),
+        ),
+    ]));
+}
+
+fn range_json(range: Range) -> Value {
+    let position = |position: Position| {
+        Value::object(vec![
+            ("line", Value::from(position.line)),
+            ("character", Value::from(position.character)),
+        ])
+    };
+    Value::object(vec![
+        ("start", position(range.start)),
+        ("end", position(range.end)),
+    ])
+}
+
+fn location_json(location: &Location) -> Value {
+    Value::object(vec![
+        ("uri", Value::from(path_to_uri(&location.file))),
+        ("range", range_json(location.range)),
+    ])
+}
+
+/// The file a `file://` URI refers to. Other URIs, such as those of unsaved
+/// documents, are used as they are.
+fn uri_to_path(uri: &str) -> String {
+    let path = match uri.strip_prefix("file://") {
+        Some(path) => path,
+        None => return String::from(uri),
+    };
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn path_to_uri(path: &str) -> String {
+    if !path.starts_with('/') {
+        return String::from(path);
+    }
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Reads one message framed by a `Content-Length` header, or `None` at the
+/// end of input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        match input.read_line(&mut header) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(error) => return Err(format!("could not read message: {}", error)),
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| String::from("message without Content-Length"))?;
+    let mut body = vec![0; length];
+    input
+        .read_exact(&mut body)
+        .map_err(|error| format!("could not read message: {}", error))?;
+    let body = String::from_utf8(body).map_err(|_| String::from("message is not UTF-8"))?;
+    Value::parse(&body).map(Some)
+}
+
+fn send(message: &Value) {
+    let body = message.to_string();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    let result = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush());
+    if result.is_err() {
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(errors: &[AssembleError]) -> ! {
+    for error in errors {
+        eprintln!("{}\n", error);
+    }
+    process::exit(1);
+}
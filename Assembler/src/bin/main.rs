@@ -1,5 +1,406 @@
-use hack_assembler::assemble;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::{env, fs, process};
+
+use hack_assembler::config::Config;
+use hack_assembler::error::AssembleError;
+use hack_assembler::formatter::format_str;
+use hack_assembler::lint;
+use hack_assembler::listing::write_listing;
+use hack_assembler::output::Format;
+use hack_assembler::parser::{Parser, ROM_SIZE};
+use hack_assembler::sourcemap::write_source_map;
+use hack_assembler::symbols::{write_symbols, SymbolFormat};
+use hack_assembler::{assemble_object, assemble_optimized, assemble_with, expand_str, lint_with};
+
+const USAGE: &str = "\
+usage: main [options] <file.asm>...
+
+Assembles each input into a file next to it, named after the output format.
+Use '-' as the input to read from stdin and write to stdout.
+
+options:
+    -o <path>          write the output to <path> ('-' for stdout); single input only
+    --format <format>  output format: hack (default), hex, bin-le, bin-be, ihex,
+                       readmemb, readmemh or logisim
+    --symbols          also write the symbol table to <name>.sym
+    --symbol-format <format>
+                       symbol table format: text (default) or json (<name>.sym.json)
+    --listing          also write a <name>.lst listing with addresses, words and source
+    --source-map       also write a <name>.map.json source map from ROM addresses to
+                       the file, line and column of each instruction
+    --config <file>    assemble for the machine described in <file>
+    --rom-size <words> fail if the program needs more than <words> of ROM (default 32768)
+    --ram-limit <address>
+                       warn when variables are allocated past <address> (default 16383)
+    --werror           treat warnings as errors
+    --object           write a relocatable <name>.hobj object for hack_linker instead
+    --lint             only check the program, warning about unused labels, likely
+                       typos, suspicious jumps and unreachable code
+    --lint-strict      like --lint, also warning about instructions such as AM=M-1
+                       that write A and read M
+    --expand           write the source with macros and includes expanded to
+                       <name>.expanded.asm instead of assembling it
+    -O, --optimize     remove redundant instructions before assembling and report
+                       how many words were saved
+    --fmt              rewrite each input in canonical form instead of assembling it
+    -h, --help         show this message";
+
+/// Exit code for inputs that failed to assemble.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid command-line usage.
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug)]
+struct Options {
+    inputs: Vec<String>,
+    output: Option<String>,
+    format: Format,
+    symbols: bool,
+    symbol_format: SymbolFormat,
+    listing: bool,
+    source_map: bool,
+    config: Option<String>,
+    rom_size: Option<usize>,
+    ram_limit: Option<u16>,
+    werror: bool,
+    object: bool,
+    lint: bool,
+    lint_strict: bool,
+    expand: bool,
+    optimize: bool,
+    fmt: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            inputs: Vec::new(),
+            output: None,
+            format: Format::Hack,
+            symbols: false,
+            symbol_format: SymbolFormat::Text,
+            listing: false,
+            source_map: false,
+            config: None,
+            rom_size: None,
+            ram_limit: None,
+            werror: false,
+            object: false,
+            lint: false,
+            lint_strict: false,
+            expand: false,
+            optimize: false,
+            fmt: false,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{} expects a value", flag))
+            };
+            match arg.as_str() {
+                "-o" | "--output" => options.output = Some(value(&arg)?),
+                "--format" => {
+                    let name = value(&arg)?;
+                    options.format = Format::from_name(&name).ok_or_else(|| {
+                        format!(
+                            "unknown format '{}', expected one of: {}",
+                            name,
+                            Format::NAMES.join(", ")
+                        )
+                    })?;
+                }
+                "--symbols" => options.symbols = true,
+                "--symbol-format" => {
+                    let name = value(&arg)?;
+                    options.symbol_format = SymbolFormat::from_name(&name)
+                        .ok_or_else(|| format!("unknown symbol format '{}'", name))?;
+                }
+                "--listing" => options.listing = true,
+                "--source-map" => options.source_map = true,
+                "--config" => options.config = Some(value(&arg)?),
+                "--rom-size" => {
+                    let words = value(&arg)?;
+                    options.rom_size = words
+                        .parse()
+                        .ok()
+                        .filter(|words| (1..=ROM_SIZE).contains(words))
+                        .map(Some)
+                        .ok_or_else(|| {
+                            format!(
+                                "--rom-size expects 1 to {} words, got '{}'",
+                                ROM_SIZE, words
+                            )
+                        })?;
+                }
+                "--ram-limit" => {
+                    let address = value(&arg)?;
+                    options.ram_limit = address.parse().map(Some).map_err(|_| {
+                        format!("--ram-limit expects an address, got '{}'", address)
+                    })?;
+                }
+                "--werror" => options.werror = true,
+                "--object" => options.object = true,
+                "--lint" => options.lint = true,
+                "--lint-strict" => {
+                    options.lint = true;
+                    options.lint_strict = true;
+                }
+                "--expand" => options.expand = true,
+                "-O" | "--optimize" => options.optimize = true,
+                "--fmt" => options.fmt = true,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                "-" => options.inputs.push(arg),
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+                _ => options.inputs.push(arg),
+            }
+        }
+
+        if options.inputs.is_empty() {
+            return Err(String::from("no input files"));
+        }
+        if options.output.is_some() && options.inputs.len() > 1 {
+            return Err(String::from("-o cannot be used with multiple input files"));
+        }
+        if options.optimize && (options.object || options.lint || options.expand) {
+            return Err(String::from(
+                "--optimize cannot be combined with --object, --lint or --expand",
+            ));
+        }
+        if options.fmt && (options.object || options.lint || options.expand || options.optimize) {
+            return Err(String::from(
+                "--fmt cannot be combined with --object, --lint, --expand or --optimize",
+            ));
+        }
+        Ok(options)
+    }
+}
 
 fn main() {
-    assemble()
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(EXIT_USAGE);
+    });
+
+    let config = load_config(&options).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(EXIT_FAILURE);
+    });
+
+    let mut failed = false;
+    for input in &options.inputs {
+        if let Err(message) = assemble_file(input, &config, &options) {
+            eprintln!("{}", message);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+/// Reads the `--config` file, if any, and applies the options that override it.
+fn load_config(options: &Options) -> Result<Config, String> {
+    let mut config = match &options.config {
+        Some(file) => {
+            let code = fs::read_to_string(file)
+                .map_err(|error| format!("error: could not read {}: {}", file, error))?;
+            Config::parse(&code, file).map_err(|errors| report(file, &errors))?
+        }
+        None => Config::default(),
+    };
+    if let Some(rom_size) = options.rom_size {
+        config.rom_size = rom_size;
+    }
+    if let Some(ram_limit) = options.ram_limit {
+        config.ram_limit = ram_limit;
+    }
+    Ok(config)
+}
+
+/// Assembles a single input according to `options`, returning a message
+/// describing why nothing was written on failure.
+fn assemble_file(input: &str, config: &Config, options: &Options) -> Result<(), String> {
+    let (code, file_name) = if input == "-" {
+        let mut code = String::new();
+        io::stdin()
+            .read_to_string(&mut code)
+            .map_err(|error| format!("error: could not read stdin: {}", error))?;
+        (code, "<stdin>")
+    } else {
+        let code = fs::read_to_string(input)
+            .map_err(|error| format!("error: could not read {}: {}", input, error))?;
+        (code, input)
+    };
+    if options.expand {
+        return expand_file(input, &code, file_name, options);
+    }
+    if options.fmt {
+        return fmt_file(input, &code, file_name, options);
+    }
+    let parser = Parser::new().with_config(config.clone());
+    if options.object {
+        return object_file(input, &code, file_name, parser, options);
+    }
+    let assembly = if options.optimize {
+        let (assembly, saved) = assemble_optimized(&code, file_name, parser)
+            .map_err(|errors| report(input, &errors))?;
+        eprintln!(
+            "{}: optimised away {} word(s), {} left",
+            input,
+            saved,
+            assembly.words.len()
+        );
+        assembly
+    } else {
+        let result = if options.lint {
+            let lints = lint::Options {
+                strict: options.lint_strict,
+            };
+            lint_with(&code, file_name, parser, lints)
+        } else {
+            assemble_with(&code, file_name, parser)
+        };
+        result.map_err(|errors| report(input, &errors))?
+    };
+
+    for warning in &assembly.warnings {
+        eprintln!("{}\n", warning);
+    }
+    if options.werror && !assembly.warnings.is_empty() {
+        return Err(format!(
+            "{}: {} warning(s) treated as errors, no output written",
+            input,
+            assembly.warnings.len()
+        ));
+    }
+
+    if options.lint {
+        return Ok(());
+    }
+
+    let output = output_file(input, options);
+    write_output(&output, |writer| {
+        options.format.write(&assembly.words, writer)
+    })?;
+
+    // Side files are named after the output, or the input when writing to stdout
+    let stem = if output == "-" { input } else { &output };
+    if options.listing {
+        let listing = output_path(stem, "lst");
+        write_output(&listing, |writer| write_listing(&assembly, writer))?;
+    }
+    if options.source_map {
+        let source_map = output_path(stem, "map.json");
+        write_output(&source_map, |writer| write_source_map(&assembly, writer))?;
+    }
+    if options.symbols {
+        let format = options.symbol_format;
+        let symbols = output_path(stem, format.extension());
+        write_output(&symbols, |writer| {
+            write_symbols(&assembly.symbols, format, writer)
+        })?;
+    }
+    Ok(())
+}
+
+fn object_file(
+    input: &str,
+    code: &str,
+    file_name: &str,
+    parser: Parser,
+    options: &Options,
+) -> Result<(), String> {
+    let (object, warnings) =
+        assemble_object(code, file_name, parser).map_err(|errors| report(input, &errors))?;
+    for warning in &warnings {
+        eprintln!("{}\n", warning);
+    }
+    if options.werror && !warnings.is_empty() {
+        return Err(format!(
+            "{}: {} warning(s) treated as errors, no output written",
+            input,
+            warnings.len()
+        ));
+    }
+
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None if input == "-" => String::from("-"),
+        None => output_path(input, "hobj"),
+    };
+    write_output(&output, |writer| object.write(writer))
+}
+
+fn expand_file(input: &str, code: &str, file_name: &str, options: &Options) -> Result<(), String> {
+    let expanded = expand_str(code, file_name).map_err(|errors| report(input, &errors))?;
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None if input == "-" => String::from("-"),
+        None => output_path(input, "expanded.asm"),
+    };
+    write_output(&output, |writer| writer.write_all(expanded.as_bytes()))
+}
+
+/// Writes the formatted source over the input, unless `-o` names another file.
+fn fmt_file(input: &str, code: &str, file_name: &str, options: &Options) -> Result<(), String> {
+    let formatted = format_str(code, file_name).map_err(|errors| report(input, &errors))?;
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => String::from(input),
+    };
+    write_output(&output, |writer| writer.write_all(formatted.as_bytes()))
+}
+
+fn report(input: &str, errors: &[AssembleError]) -> String {
+    for error in errors {
+        eprintln!("{}\n", error);
+    }
+    format!(
+        "{}: {} error(s) found, no output written",
+        input,
+        errors.len()
+    )
+}
+
+/// Where the machine code for `input` goes.
+fn output_file(input: &str, options: &Options) -> String {
+    match &options.output {
+        Some(output) => output.clone(),
+        None if input == "-" => String::from("-"),
+        None => output_path(input, options.format.extension()),
+    }
+}
+
+fn output_path(input: &str, extension: &str) -> String {
+    if input == "-" {
+        return format!("stdin.{}", extension);
+    }
+    Path::new(input)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Runs `write` against the file at `path`, or stdout when `path` is `-`.
+fn write_output(
+    path: &str,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), String> {
+    let result = if path == "-" {
+        let stdout = io::stdout();
+        let mut writer = stdout.lock();
+        write(&mut writer).and_then(|_| writer.flush())
+    } else {
+        fs::File::create(path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer).and_then(|_| writer.flush())
+        })
+    };
+    result.map_err(|error| format!("error: could not write {}: {}", path, error))
 }
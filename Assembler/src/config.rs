@@ -0,0 +1,251 @@
+use std::ops::RangeInclusive;
+
+use crate::error::AssembleError;
+use crate::isa::InstructionSet;
+use crate::lexer::is_symbol;
+use crate::parser::{MAX_ADDRESS, RAM_LIMIT, ROM_SIZE};
+
+/// The machine a program is assembled for. The default is the standard Hack
+/// computer; a config file can describe extended machines or teaching
+/// variants:
+///
+/// ```text
+/// # Hack with 16K of ROM and a memory-mapped LED
+/// rom_size = 16384
+/// variable_base = 32
+/// reserve = 256..2047
+/// implicit_variables = true
+/// extensions = shift
+///
+/// [symbols]
+/// LED = 24577
+///
+/// [instructions]
+/// D*A = 0b1000000000
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// How many instructions fit in ROM.
+    pub rom_size: usize,
+    /// The highest RAM address variables may be allocated at before a warning.
+    pub ram_limit: u16,
+    /// The first RAM address allocated to a variable.
+    pub variable_base: u16,
+    /// RAM addresses variable allocation skips.
+    pub reserved: Vec<RangeInclusive<u16>>,
+    /// Whether an undefined symbol becomes a variable, or is an error.
+    pub implicit_variables: bool,
+    /// Whether `SP`, `LCL`, ..., `SCREEN` and `KBD` are predefined.
+    pub standard_symbols: bool,
+    /// Predefined symbols in addition to, or replacing, the standard ones.
+    pub symbols: Vec<(String, u16)>,
+    /// Comps the machine understands beyond the standard ones.
+    pub instruction_set: InstructionSet,
+}
+
+/// The part of a config file being read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Settings,
+    Symbols,
+    Instructions,
+}
+
+impl Config {
+    /// Reads a config file of `key = value` lines, followed by optional
+    /// `[symbols]` and `[instructions]` sections of `NAME = address` and
+    /// `COMP = encoding` lines. `#` starts a comment. Numbers are decimal,
+    /// `0x` hex or `0b` binary. `file` is only used to label errors.
+    pub fn parse(code: &str, file: &str) -> Result<Config, Vec<AssembleError>> {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        let mut section = Section::Settings;
+        for (index, source) in code.lines().enumerate() {
+            let line = source.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| {
+                let column = source.len() - source.trim_start().len() + 1;
+                AssembleError::new(
+                    message,
+                    file,
+                    index + 1,
+                    column,
+                    line.chars().count(),
+                    source,
+                )
+            };
+            if line.starts_with('[') {
+                match line {
+                    "[symbols]" => section = Section::Symbols,
+                    "[instructions]" => section = Section::Instructions,
+                    _ => errors.push(error(format!("unknown section '{}'", line))),
+                }
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    errors.push(error(String::from("expected 'key = value'")));
+                    continue;
+                }
+            };
+
+            let result = match section {
+                Section::Settings => config.set(key, value),
+                Section::Symbols if is_symbol(key) => {
+                    address(value).map(|address| config.symbols.push((String::from(key), address)))
+                }
+                Section::Symbols => Err(format!("invalid symbol name '{}'", key)),
+                Section::Instructions => number(value)
+                    .filter(|bits| *bits <= u16::MAX as u32)
+                    .ok_or_else(|| format!("invalid encoding '{}'", value))
+                    .and_then(|bits| config.instruction_set.add_comp(key, bits as u16)),
+            };
+            if let Err(message) = result {
+                errors.push(error(message));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "rom_size" => {
+                self.rom_size = number(value)
+                    .filter(|size| (1..=ROM_SIZE as u32).contains(size))
+                    .ok_or_else(|| format!("rom_size must be 1 to {}, got '{}'", ROM_SIZE, value))?
+                    as usize
+            }
+            "ram_limit" => self.ram_limit = address(value)?,
+            "variable_base" => self.variable_base = address(value)?,
+            "reserve" => {
+                let (start, end) = value
+                    .split_once("..")
+                    .ok_or_else(|| format!("expected 'start..end', got '{}'", value))?;
+                let (start, end) = (address(start.trim())?, address(end.trim())?);
+                if start > end {
+                    return Err(format!("empty range '{}'", value));
+                }
+                self.reserved.push(start..=end);
+            }
+            "implicit_variables" => self.implicit_variables = boolean(value)?,
+            "standard_symbols" => self.standard_symbols = boolean(value)?,
+            "extensions" => {
+                for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                    self.instruction_set.add_extension(name)?;
+                }
+            }
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// The first address at or after `address` that is not reserved.
+    fn skip_reserved(&self, mut address: u32) -> u32 {
+        while let Some(region) = self
+            .reserved
+            .iter()
+            .find(|region| (*region.start() as u32..=*region.end() as u32).contains(&address))
+        {
+            address = *region.end() as u32 + 1;
+        }
+        address
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            rom_size: ROM_SIZE,
+            ram_limit: RAM_LIMIT,
+            variable_base: 16,
+            reserved: Vec::new(),
+            implicit_variables: true,
+            standard_symbols: true,
+            symbols: Vec::new(),
+            instruction_set: InstructionSet::new(),
+        }
+    }
+}
+
+/// Hands out RAM addresses to variables in order, skipping reserved regions.
+#[derive(Debug, Clone)]
+pub struct Allocator {
+    next: u32,
+    past_limit: bool,
+}
+
+/// A block of RAM handed out by [`Allocator::allocate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub address: u16,
+    /// Whether this is the first allocation that reaches past the RAM limit.
+    pub past_limit: bool,
+}
+
+impl Allocator {
+    pub fn new(config: &Config) -> Allocator {
+        Allocator {
+            next: config.variable_base as u32,
+            past_limit: false,
+        }
+    }
+
+    /// The start of the next `size` free, contiguous addresses, or `None`
+    /// once RAM is exhausted.
+    pub fn allocate(&mut self, config: &Config, size: u16) -> Option<Allocation> {
+        let mut address = config.skip_reserved(self.next);
+        let last = loop {
+            let last = address + size.max(1) as u32 - 1;
+            // A block must not straddle a reserved region either
+            match config.reserved.iter().find(|region| {
+                *region.start() as u32 <= last && address <= *region.end() as u32
+            }) {
+                Some(region) => address = config.skip_reserved(*region.end() as u32 + 1),
+                None => break last,
+            }
+        };
+        if last > MAX_ADDRESS as u32 {
+            return None;
+        }
+        let past_limit = last > config.ram_limit as u32 && !self.past_limit;
+        self.past_limit |= past_limit;
+        self.next = last + 1;
+        Some(Allocation {
+            address: address as u16,
+            past_limit,
+        })
+    }
+}
+
+fn number(value: &str) -> Option<u32> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = value.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+fn address(value: &str) -> Result<u16, String> {
+    number(value)
+        .filter(|address| *address <= u16::MAX as u32)
+        .map(|address| address as u16)
+        .ok_or_else(|| format!("invalid address '{}'", value))
+}
+
+fn boolean(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected 'true' or 'false', got '{}'", value)),
+    }
+}
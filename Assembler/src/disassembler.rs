@@ -0,0 +1,268 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::AssembleError;
+use crate::isa::InstructionSet;
+use crate::lexer::{AValue, Comp, Dest, Instruction, Jump};
+use crate::parser::{encode_comp, encode_dest, encode_jump};
+
+/// Turns Hack machine code back into assembly, using the inverse of the
+/// encodings in [`parser`](crate::parser).
+#[derive(Debug)]
+pub struct Disassembler {
+    comp: HashMap<u16, Comp>,
+    dest: HashMap<u16, Dest>,
+    jump: HashMap<u16, Jump>,
+    label_jumps: bool,
+    symbols: SymbolMap,
+}
+
+/// Names to restore while disassembling, keyed by address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    /// Labels, keyed by ROM address.
+    pub labels: HashMap<u16, String>,
+    /// Variables, keyed by RAM address.
+    pub variables: HashMap<u16, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    /// The reconstructed assembly source.
+    pub source: String,
+    /// ROM addresses of words that are not legal Hack instructions.
+    pub illegal: Vec<usize>,
+}
+
+impl Disassembler {
+    pub fn new() -> Disassembler {
+        Disassembler {
+            comp: Comp::STANDARD
+                .iter()
+                .filter_map(|comp| Some((encode_comp(*comp)?, *comp)))
+                .collect(),
+            dest: Dest::ALL.iter().map(|dest| (encode_dest(*dest), *dest)).collect(),
+            jump: Jump::ALL.iter().map(|jump| (encode_jump(*jump), *jump)).collect(),
+            label_jumps: false,
+            symbols: SymbolMap::default(),
+        }
+    }
+
+    /// When enabled, every jump target without a known name gets a
+    /// generated label such as `(L_0042)`.
+    pub fn label_jumps(mut self, enabled: bool) -> Disassembler {
+        self.label_jumps = enabled;
+        self
+    }
+
+    pub fn with_symbols(mut self, symbols: SymbolMap) -> Disassembler {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Also decodes the extended comps of `instruction_set`.
+    pub fn with_instruction_set(mut self, instruction_set: &InstructionSet) -> Disassembler {
+        for (comp, bits) in instruction_set.get_comps() {
+            self.comp.insert(*bits, *comp);
+        }
+        self
+    }
+
+    /// Decodes a single word, or returns `None` if it is not a legal instruction.
+    pub fn decode(&self, word: u16) -> Option<Instruction<'static>> {
+        if word & 0x8000 == 0 {
+            return Some(Instruction::AInstruction(AValue::Literal(word)));
+        }
+        // The comp is keyed by its prefix too, so words with an unknown prefix are illegal
+        Some(Instruction::CInstruction {
+            comp: *self.comp.get(&(word >> 6))?,
+            dest: self.dest[&(word >> 3 & 0b111)],
+            jump: self.jump[&(word & 0b111)],
+        })
+    }
+
+    pub fn disassemble(&self, words: &[u16]) -> Disassembly {
+        let decoded: Vec<_> = words.iter().map(|word| self.decode(*word)).collect();
+
+        // An A-instruction directly followed by a jump loads a ROM address.
+        let mut jump_loads = HashSet::new();
+        for (address, pair) in decoded.windows(2).enumerate() {
+            if let [Some(Instruction::AInstruction(_)), Some(Instruction::CInstruction { jump, .. })] =
+                pair
+            {
+                if *jump != Jump::Null {
+                    jump_loads.insert(address);
+                }
+            }
+        }
+
+        // Only labels in the program are declared, so a jump past its end
+        // keeps its number rather than naming a label that would become a
+        // variable when reassembled
+        let mut labels = self.symbols.labels.clone();
+        labels.retain(|address, _| (*address as usize) <= words.len());
+        if self.label_jumps {
+            for address in &jump_loads {
+                if let Some(Instruction::AInstruction(AValue::Literal(target))) = &decoded[*address]
+                {
+                    if (*target as usize) < words.len() {
+                        labels
+                            .entry(*target)
+                            .or_insert_with(|| format!("L_{:04}", target));
+                    }
+                }
+            }
+        }
+
+        let mut source = String::new();
+        let mut illegal = Vec::new();
+        for (address, instruction) in decoded.into_iter().enumerate() {
+            if let Some(label) = labels.get(&(address as u16)) {
+                source.push_str(&format!("({})\n", label));
+            }
+            let instruction = match instruction {
+                Some(Instruction::AInstruction(AValue::Literal(value))) => {
+                    let name = if jump_loads.contains(&address) {
+                        labels.get(&value)
+                    } else {
+                        self.symbols.variables.get(&value)
+                    };
+                    match name {
+                        Some(name) => Instruction::AInstruction(AValue::Symbol(Cow::Owned(name.clone()))),
+                        None => Instruction::AInstruction(AValue::Literal(value)),
+                    }
+                }
+                Some(instruction) => instruction,
+                None => {
+                    source.push_str(&format!("// illegal instruction {:016b}\n", words[address]));
+                    illegal.push(address);
+                    continue;
+                }
+            };
+            source.push_str(&format!("{}\n", instruction));
+        }
+        if let Some(label) = labels.get(&(words.len() as u16)) {
+            source.push_str(&format!("({})\n", label));
+        }
+
+        Disassembly { source, illegal }
+    }
+}
+
+impl Default for Disassembler {
+    fn default() -> Disassembler {
+        Disassembler::new()
+    }
+}
+
+/// Reads a `.hack` file: one instruction per line written as 16 binary digits.
+pub fn read_hack(code: &str, file_name: &str) -> Result<Vec<u16>, Vec<AssembleError>> {
+    let mut words = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in code.lines().enumerate() {
+        let word = line.trim();
+        if word.is_empty() {
+            continue;
+        }
+        if word.len() == 16 && word.bytes().all(|bit| bit == b'0' || bit == b'1') {
+            words.push(u16::from_str_radix(word, 2).unwrap());
+        } else {
+            let indent = line.len() - line.trim_start().len();
+            let column = line[..indent].chars().count() + 1;
+            errors.push(AssembleError::new(
+                String::from("expected a word of 16 binary digits"),
+                file_name,
+                index + 1,
+                column,
+                word.chars().count(),
+                line,
+            ))
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(words)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The 1-based column where whitespace-separated field number `field` starts.
+fn field_column(line: &str, field: usize) -> usize {
+    let mut rest = line;
+    for _ in 0..field {
+        rest = rest.trim_start();
+        rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+    }
+    line[..line.len() - rest.trim_start().len()].chars().count() + 1
+}
+
+/// Reads a symbol map with one `<kind> <name> <address> [size]` entry per
+/// line, where `kind` is `label` or `variable`. Entries of any other kind are
+/// ignored, and the elements of an array are named `<name>+<offset>`.
+pub fn read_symbol_map(code: &str, file_name: &str) -> Result<SymbolMap, Vec<AssembleError>> {
+    let mut symbols = SymbolMap::default();
+    let mut errors = Vec::new();
+    for (index, line) in code.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (kind, name, address, size) = match fields.as_slice() {
+            [] => continue,
+            [kind, name, address] => (*kind, *name, *address, "1"),
+            [kind, name, address, size] => (*kind, *name, *address, *size),
+            _ => {
+                errors.push(AssembleError::new(
+                    String::from("expected '<kind> <name> <address> [size]'"),
+                    file_name,
+                    index + 1,
+                    1,
+                    line.chars().count(),
+                    line,
+                ));
+                continue;
+            }
+        };
+        let field_error = |field: usize, message: String| {
+            let value = fields[field];
+            AssembleError::new(
+                message,
+                file_name,
+                index + 1,
+                field_column(line, field),
+                value.chars().count(),
+                line,
+            )
+        };
+        let address = match address.parse::<u16>() {
+            Ok(address) => address,
+            Err(_) => {
+                errors.push(field_error(2, format!("invalid address '{}'", address)));
+                continue;
+            }
+        };
+        let size = match size.parse::<u16>() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                errors.push(field_error(3, format!("invalid size '{}'", size)));
+                continue;
+            }
+        };
+        let table = match kind {
+            "label" => &mut symbols.labels,
+            "variable" => &mut symbols.variables,
+            _ => continue,
+        };
+        table.entry(address).or_insert_with(|| String::from(name));
+        // Name the elements of an array after its start
+        for offset in 1..size {
+            table
+                .entry(address.wrapping_add(offset))
+                .or_insert_with(|| format!("{}+{}", name, offset));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(symbols)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::fmt;
+use std::iter;
+
+/// Whether a diagnostic stops assembly or is only reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while assembling, pointing at the offending source text.
+/// Warnings use the same type with a [`Severity::Warning`] severity.
+///
+/// `line` and `column` are 1-based, and `width` is the number of characters
+/// underlined in the rendered diagnostic. Errors about a file as a whole,
+/// such as failing to read it, have a `line` of 0 and no source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub width: usize,
+    pub source_line: String,
+    /// Extra context printed below the source, such as the macro call a
+    /// line was expanded from.
+    pub notes: Vec<String>,
+}
+
+impl AssembleError {
+    pub fn new(
+        message: String,
+        file: &str,
+        line: usize,
+        column: usize,
+        width: usize,
+        source_line: &str,
+    ) -> AssembleError {
+        AssembleError {
+            severity: Severity::Error,
+            message,
+            file: String::from(file),
+            line,
+            column,
+            width,
+            source_line: String::from(source_line),
+            notes: Vec::new(),
+        }
+    }
+
+    /// An error that concerns `file` as a whole rather than a line in it.
+    pub fn file(message: String, file: &str) -> AssembleError {
+        AssembleError::new(message, file, 0, 0, 0, "")
+    }
+
+    pub fn with_note(mut self, note: String) -> AssembleError {
+        self.notes.push(note);
+        self
+    }
+
+    /// Downgrades the error to a warning.
+    pub fn into_warning(self) -> AssembleError {
+        AssembleError {
+            severity: Severity::Warning,
+            ..self
+        }
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.severity == Severity::Warning
+    }
+}
+
+impl fmt::Display for AssembleError {
+    /// Renders the error with the source line and a caret under the problem:
+    ///
+    /// ```text
+    /// error: unknown comp 'D+X'
+    ///  --> Program.asm:3:3
+    ///   |
+    /// 3 | D=D+X
+    ///   |   ^^^
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.line == 0 {
+            write!(f, "{}: {}\n --> {}", severity, self.message, self.file)?;
+            for note in &self.notes {
+                write!(f, "\n  = note: {}", note)?;
+            }
+            return Ok(());
+        }
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{}: {}", severity, self.message)?;
+        writeln!(f, "{}--> {}:{}:{}", gutter, self.file, self.line, self.column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        // Tabs are copied so the caret lines up however wide they are shown
+        let indent: String = self
+            .source_line
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .chain(iter::repeat(' '))
+            .take(self.column.saturating_sub(1))
+            .collect();
+        write!(f, "{} | {}{}", gutter, indent, "^".repeat(self.width.max(1)))?;
+        for note in &self.notes {
+            write!(f, "\n{} = note: {}", gutter, note)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for AssembleError {}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+use crate::lexer::is_symbol_char;
+
+/// A constant expression in an A-instruction, such as `SCREEN+32` or `(KBD-1)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i32),
+    Symbol(String),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+}
+
+/// A syntax error at byte `offset` in the expression text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub offset: usize,
+    pub width: usize,
+    pub message: String,
+}
+
+impl Expr {
+    /// Parses `+`, `-`, unary minus and parentheses over decimal, `0x` hex and
+    /// `0b` binary literals and symbols.
+    pub fn parse(text: &str) -> Result<Expr, ExprError> {
+        let mut parser = ExprParser { text, position: 0 };
+        let expr = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.position < text.len() {
+            return Err(parser.error_here("unexpected trailing characters"));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression, resolving symbols with `lookup`.
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<i32>) -> Result<i32, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name) => {
+                lookup(name).ok_or_else(|| format!("undefined symbol '{}' in expression", name))
+            }
+            Expr::Negate(operand) => operand
+                .eval(lookup)?
+                .checked_neg()
+                .ok_or_else(|| String::from("arithmetic overflow in expression")),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(lookup)?, right.eval(lookup)?);
+                match op {
+                    BinaryOp::Add => left.checked_add(right),
+                    BinaryOp::Sub => left.checked_sub(right),
+                }
+                .ok_or_else(|| String::from("arithmetic overflow in expression"))
+            }
+        }
+    }
+
+    /// The symbols the expression refers to, in order of appearance.
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) => Vec::new(),
+            Expr::Symbol(name) => vec![name.as_str()],
+            Expr::Negate(operand) => operand.symbols(),
+            Expr::Binary(_, left, right) => {
+                let mut symbols = left.symbols();
+                symbols.extend(right.symbols());
+                symbols
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{}", value),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Negate(operand) => match **operand {
+                Expr::Binary(..) => write!(f, "-({})", operand),
+                _ => write!(f, "-{}", operand),
+            },
+            Expr::Binary(op, left, right) => {
+                let op = match op {
+                    BinaryOp::Add => '+',
+                    BinaryOp::Sub => '-',
+                };
+                match **right {
+                    Expr::Binary(..) => write!(f, "{}{}({})", left, op, right),
+                    _ => write!(f, "{}{}{}", left, op, right),
+                }
+            }
+        }
+    }
+}
+
+struct ExprParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl ExprParser<'_> {
+    fn expression(&mut self) -> Result<Expr, ExprError> {
+        let mut expr = self.term()?;
+        loop {
+            self.skip_whitespace();
+            let op = match self.peek() {
+                Some('+') => BinaryOp::Add,
+                Some('-') => BinaryOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.position += 1;
+            let right = self.term()?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(right));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ExprError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(Expr::Negate(Box::new(self.term()?)))
+            }
+            Some('(') => {
+                let open = self.position;
+                self.position += 1;
+                let expr = self.expression()?;
+                self.skip_whitespace();
+                if self.peek() != Some(')') {
+                    return Err(ExprError {
+                        offset: open,
+                        width: 1,
+                        message: String::from("unclosed '('"),
+                    });
+                }
+                self.position += 1;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() => self.number(),
+            Some(c) if is_symbol_start(c) => {
+                let word = self.word();
+                Ok(Expr::Symbol(String::from(word)))
+            }
+            Some(_) => Err(self.error_here("expected a number, symbol or '('")),
+            None => Err(self.error_here("expected a number, symbol or '(' at end of expression")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, ExprError> {
+        let start = self.position;
+        let word = self.word();
+        let (digits, radix) = if let Some(hex) = word.strip_prefix("0x") {
+            (hex, 16)
+        } else if let Some(binary) = word.strip_prefix("0b") {
+            (binary, 2)
+        } else {
+            (word, 10)
+        };
+        i32::from_str_radix(digits, radix)
+            .map(Expr::Number)
+            .map_err(|_| ExprError {
+                offset: start,
+                width: word.len(),
+                message: format!("invalid number '{}'", word),
+            })
+    }
+
+    /// Consumes a run of symbol characters, which also covers number literals.
+    fn word(&mut self) -> &str {
+        let start = self.position;
+        let rest = &self.text[start..];
+        let len = rest
+            .find(|c: char| !is_symbol_char(c))
+            .unwrap_or(rest.len());
+        self.position += len;
+        &self.text[start..self.position]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn error_here(&self, message: &str) -> ExprError {
+        ExprError {
+            offset: self.position,
+            width: self.peek().map_or(0, char::len_utf8),
+            message: String::from(message),
+        }
+    }
+}
+
+fn is_symbol_start(c: char) -> bool {
+    !c.is_ascii_digit() && is_symbol_char(c)
+}
@@ -0,0 +1,124 @@
+use crate::error::AssembleError;
+use crate::lexer::{source_lines, AValue, Directive, Instruction, LineContent, SourceLine};
+
+/// A formatted line as its code and trailing comment, or `None` for a blank line.
+type Entry = Option<(String, Option<String>)>;
+
+/// How far instructions are indented.
+const INDENT: &str = "    ";
+
+/// Rewrites Hack assembly in canonical form: labels and directives flush
+/// left, instructions and macro calls indented, comp fields in their
+/// canonical spelling and A-instructions without whitespace. Comments are
+/// kept with one space after `//`, trailing comments on consecutive lines
+/// are aligned, and runs of blank lines are collapsed into one. `file_name`
+/// only labels diagnostics.
+pub fn format_str(code: &str, file_name: &str) -> Result<String, Vec<AssembleError>> {
+    let lines = source_lines(code, file_name)?;
+
+    let mut rendered: Vec<Entry> = Vec::new();
+    for line in &lines {
+        let comment = line.comment.as_deref().map(format_comment);
+        let entry = match (&line.content, comment) {
+            (LineContent::Empty, None) => None,
+            (LineContent::Empty, Some(comment)) => {
+                let indent = if line.indented { INDENT } else { "" };
+                Some((format!("{}{}", indent, comment), None))
+            }
+            (_, comment) => Some((format_code(line), comment)),
+        };
+        if entry.is_none() && matches!(rendered.last(), None | Some(None)) {
+            continue;
+        }
+        rendered.push(entry);
+    }
+    if rendered.last().is_some_and(Option::is_none) {
+        rendered.pop();
+    }
+
+    // Trailing comments on consecutive lines line up
+    let commented = |entry: &Entry| matches!(entry, Some((_, Some(_))));
+    let mut formatted = String::new();
+    let mut start = 0;
+    while start < rendered.len() {
+        let mut end = start + 1;
+        while end < rendered.len() && commented(&rendered[end - 1]) && commented(&rendered[end]) {
+            end += 1;
+        }
+        let run = &rendered[start..end];
+        start = end;
+        let width = run
+            .iter()
+            .flatten()
+            .map(|(code, _)| code.chars().count())
+            .max()
+            .unwrap_or(0);
+        for entry in run {
+            match entry {
+                Some((code, Some(comment))) => {
+                    formatted.push_str(&format!("{:width$} {}", code, comment, width = width))
+                }
+                Some((code, None)) => formatted.push_str(code),
+                None => {}
+            }
+            formatted.push('\n');
+        }
+    }
+    Ok(formatted)
+}
+
+fn format_code(line: &SourceLine) -> String {
+    match &line.content {
+        LineContent::Instruction(Instruction::LInstruction(label)) => format!("({})", label),
+        // The operand is kept as written, so `@0x4000` is not turned into `@16384`
+        LineContent::Instruction(Instruction::AInstruction(value)) => {
+            let operand: String = match value {
+                AValue::Symbol(symbol) => symbol.to_string(),
+                _ => line.code[1..].split_whitespace().collect(),
+            };
+            format!("{}@{}", INDENT, operand)
+        }
+        LineContent::Instruction(Instruction::Directive(call @ Directive::MacroCall { .. })) => {
+            format!("{}{}", INDENT, call)
+        }
+        LineContent::Instruction(Instruction::Directive(_)) => {
+            let (name, arguments) = line
+                .code
+                .split_once(char::is_whitespace)
+                .unwrap_or((&line.code, ""));
+            // A path may contain spaces, other arguments are separated by one
+            let arguments = match name {
+                ".include" => String::from(arguments.trim()),
+                _ => arguments.split_whitespace().collect::<Vec<_>>().join(" "),
+            };
+            match arguments.as_str() {
+                "" => String::from(name),
+                arguments => format!("{} {}", name, arguments),
+            }
+        }
+        LineContent::Instruction(instruction) => format!("{}{}", INDENT, instruction),
+        LineContent::MacroStart { name, params } if params.is_empty() => {
+            format!(".macro {}", name)
+        }
+        LineContent::MacroStart { name, params } => {
+            format!(".macro {} {}", name, params.join(", "))
+        }
+        LineContent::MacroEnd => String::from(".endm"),
+        LineContent::MacroBody if line.code.starts_with('(') => line.code.clone(),
+        LineContent::MacroBody => format!("{}{}", INDENT, line.code),
+        LineContent::Empty => String::new(),
+    }
+}
+
+/// Puts exactly one space between `//` and the comment text. Comments
+/// starting with more slashes, such as `////` banners, are kept as written.
+fn format_comment(text: &str) -> String {
+    let text = text.trim_end();
+    if text.is_empty() {
+        String::from("//")
+    } else if text.starts_with('/') {
+        format!("//{}", text)
+    } else {
+        format!("// {}", text.trim_start())
+    }
+}
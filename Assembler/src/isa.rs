@@ -0,0 +1,102 @@
+use crate::lexer::Comp;
+use crate::parser::encode_comp;
+
+/// The shift operations of the extended Hack CPU, which use the `101` prefix
+/// left free by the standard `111`. Encodings are bits 15..6 of the word.
+const SHIFT: [(&str, u16); 6] = [
+    ("A<<", 0b1010100000),
+    ("D<<", 0b1010110000),
+    ("M<<", 0b1011100000),
+    ("A>>", 0b1010000000),
+    ("D>>", 0b1010010000),
+    ("M>>", 0b1011000000),
+];
+
+/// Comps a machine understands beyond the standard Hack set, and how each
+/// is encoded. The parser, disassembler and anything else that encodes or
+/// decodes words extend their tables from the same description, so they
+/// always agree. An extended comp fills bits 15..6 of a C-instruction; the
+/// dest and jump fields are encoded as usual.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstructionSet {
+    comps: Vec<(Comp, u16)>,
+}
+
+impl InstructionSet {
+    /// The standard Hack instruction set, with no extensions.
+    pub fn new() -> InstructionSet {
+        InstructionSet::default()
+    }
+
+    /// Adds a named group of comps. The only one is `shift`, for `A<<`,
+    /// `D<<`, `M<<`, `A>>`, `D>>` and `M>>`.
+    pub fn add_extension(&mut self, name: &str) -> Result<(), String> {
+        let comps = match name {
+            "shift" => &SHIFT,
+            _ => return Err(format!("unknown extension '{}'", name)),
+        };
+        for (mnemonic, bits) in comps {
+            self.add_comp(mnemonic, *bits)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the comp `mnemonic`, encoded as `bits`, the 10 high bits of the
+    /// word. The top bit must be set, as for every C-instruction, and
+    /// neither the mnemonic nor the encoding may be in use already.
+    pub fn add_comp(&mut self, mnemonic: &str, bits: u16) -> Result<(), String> {
+        if Comp::from_mnemonic(mnemonic).is_some() {
+            return Err(format!("'{}' is a standard comp", mnemonic));
+        }
+        let comp = Comp::extended(mnemonic)
+            .ok_or_else(|| format!("invalid comp mnemonic '{}'", mnemonic))?;
+        if !(0x200..=0x3ff).contains(&bits) {
+            return Err(format!(
+                "encoding of '{}' must be 10 bits starting with 1, got {:#b}",
+                mnemonic, bits
+            ));
+        }
+
+        let taken = Comp::STANDARD
+            .iter()
+            .filter_map(|comp| Some((comp, encode_comp(*comp)?)))
+            .chain(self.comps.iter().map(|(comp, bits)| (comp, *bits)))
+            .find(|(other, other_bits)| **other == comp || *other_bits == bits);
+        match taken {
+            Some((other, _)) if *other == comp => Err(format!("'{}' is already defined", mnemonic)),
+            Some((other, _)) => Err(format!(
+                "encoding {:#b} of '{}' is already used by '{}'",
+                bits,
+                mnemonic,
+                other.mnemonic()
+            )),
+            None => {
+                self.comps.push((comp, bits));
+                Ok(())
+            }
+        }
+    }
+
+    /// The extended comps and their encodings, in the order they were added.
+    pub fn get_comps(&self) -> &[(Comp, u16)] {
+        &self.comps
+    }
+
+    /// Bits 15..6 of a C-instruction for the extended comp `comp`, or `None`
+    /// if it is not part of this set.
+    pub fn encode(&self, comp: Comp) -> Option<u16> {
+        self.comps
+            .iter()
+            .find(|(other, _)| *other == comp)
+            .map(|(_, bits)| *bits)
+    }
+}
+
+/// The extension that defines `comp`, for suggesting it when a program uses
+/// the comp without enabling the extension.
+pub fn extension_of(comp: Comp) -> Option<&'static str> {
+    SHIFT
+        .iter()
+        .any(|(mnemonic, _)| *mnemonic == comp.mnemonic())
+        .then_some("shift")
+}
@@ -1,82 +1,1474 @@
-pub struct Lexer {
-    tokens: Vec<Token>,
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::error::AssembleError;
+use crate::expr::{BinaryOp, Expr};
+use crate::macros::Macro;
+use crate::parser::MAX_ADDRESS;
+
+pub struct Lexer<'a> {
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    /// Tokenizes `code`, collecting an error for every malformed line.
+    /// `file_name` labels diagnostics and is the base for relative `.include` paths.
+    /// Macros are expanded here, so the tokens never contain a macro body.
+    /// Tokens borrow their source line and names from `code`; only the lines
+    /// of included files and macro expansions are copied.
+    pub fn new(code: &'a str, file_name: &str) -> Result<Lexer<'a>, Vec<AssembleError>> {
+        let tokens = lex_lines(split_lines(code).map(Cow::Borrowed), file_name)?;
+        Ok(Lexer { tokens })
+    }
+
+    pub fn get_tokens(&self) -> &Vec<Token<'a>> {
+        &self.tokens
+    }
+
+    pub fn into_tokens(self) -> Vec<Token<'a>> {
+        self.tokens
+    }
+}
+
+fn lex_lines<'a>(
+    lines: impl Iterator<Item = Cow<'a, str>>,
+    file_name: &str,
+) -> Result<Vec<Token<'a>>, Vec<AssembleError>> {
+    let mut state = LexState {
+        tokens: Vec::new(),
+        errors: Vec::new(),
+        includes: vec![Include::new(Path::new(file_name))],
+        macros: HashMap::new(),
+        expansions: 0,
+    };
+    state.lex(lines, file_name);
+    state.data_prologue();
+
+    if state.errors.is_empty() {
+        Ok(state.tokens)
+    } else {
+        Err(state.errors)
+    }
+}
+
+/// Lexes `code` with the lines that `errors` point at blanked out, so one
+/// bad line does not hide every symbol. Blanking a line may expose errors
+/// further on, such as an `.endm` without its `.macro`, so this repeats
+/// until the rest lexes or there is nothing left to blank.
+pub(crate) fn recover_tokens<'a>(
+    code: &'a str,
+    file: &str,
+    errors: &[AssembleError],
+) -> Vec<Token<'a>> {
+    let mut lines: Vec<&str> = split_lines(code).collect();
+    let mut errors = errors.to_vec();
+    loop {
+        let mut blanked = false;
+        for error in errors.iter().filter(|error| error.file == file) {
+            if let Some(line) = lines.get_mut(error.line.wrapping_sub(1)) {
+                blanked |= !line.is_empty();
+                *line = "";
+            }
+        }
+        if !blanked {
+            return Vec::new();
+        }
+        match lex_lines(lines.iter().map(|line| Cow::Borrowed(*line)), file) {
+            Ok(tokens) => return tokens,
+            Err(found) => errors = found,
+        }
+    }
+}
+
+/// A line of source as written, as read by [`source_lines`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The 1-based line number.
+    pub number: usize,
+    /// The line without its comment and surrounding whitespace.
+    pub code: String,
+    pub content: LineContent,
+    /// The text after `//`, if the line has a comment.
+    pub comment: Option<String>,
+    /// Whether the line starts with whitespace.
+    pub indented: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineContent {
+    /// A blank line, or one with only a comment.
+    Empty,
+    /// An instruction, label or directive. Macro calls are recognised by
+    /// their shape, since the macro may be defined in an included file.
+    Instruction(Instruction<'static>),
+    /// `.macro NAME param, ...`
+    MacroStart { name: String, params: Vec<String> },
+    /// `.endm`
+    MacroEnd,
+    /// A line of a macro body that is only an instruction once its
+    /// parameters are substituted, such as `@\src`.
+    MacroBody,
 }
 
-impl Lexer {
-    pub fn new(code: String) -> Lexer {
-        let mut tokens = Vec::new();
-        for line in code.lines() {
-            match preprocess_code(String::from(line)) {
-                Some(clean_line) => tokens.push(Token::new(clean_line)),
+/// Reads `code` line by line for tools that rewrite source rather than
+/// assemble it. Unlike [`Lexer::new`] it keeps blank lines and comments, and
+/// leaves macros and includes unexpanded. `file_name` only labels diagnostics.
+pub fn source_lines(code: &str, file_name: &str) -> Result<Vec<SourceLine>, Vec<AssembleError>> {
+    let file: Rc<str> = Rc::from(file_name);
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    let mut open_macro = None;
+    for (index, source) in split_lines(code).enumerate() {
+        let (code, comment) = split_comment(source);
+        let content = match Line::new(&file, index + 1, Cow::Borrowed(source), None) {
+            Some(line) => match line_content(&line, &mut open_macro) {
+                Ok(content) => content,
+                Err(error) => {
+                    errors.push(line.error(error));
+                    continue;
+                }
+            },
+            None => LineContent::Empty,
+        };
+        lines.push(SourceLine {
+            number: index + 1,
+            code: String::from(code.trim()),
+            content,
+            comment: comment.map(String::from),
+            indented: source.starts_with(char::is_whitespace),
+        });
+    }
+
+    errors.extend(open_macro);
+
+    if errors.is_empty() {
+        Ok(lines)
+    } else {
+        Err(errors)
+    }
+}
+
+/// What `line` holds, for [`source_lines`]. `open_macro` is the error to
+/// report if the macro being defined is never closed.
+fn line_content(
+    line: &Line,
+    open_macro: &mut Option<AssembleError>,
+) -> Result<LineContent, LineError> {
+    let clean = line.clean();
+    match directive_name(clean) {
+        ".macro" if open_macro.is_some() => Err(LineError::new(
+            0,
+            6,
+            String::from("macros cannot be defined inside another macro"),
+        )),
+        ".macro" => parse_macro_header(clean).map(|(name, params)| {
+            // Reported if the file ends before `.endm`
+            let message = format!("macro '{}' has no matching '.endm'", name);
+            let unclosed = LineError::new(0, clean.len(), message);
+            *open_macro = Some(line.error(unclosed));
+            LineContent::MacroStart { name, params }
+        }),
+        ".endm" if open_macro.is_some() => {
+            *open_macro = None;
+            Ok(LineContent::MacroEnd)
+        }
+        _ => match parse_instruction(clean) {
+            Ok(instruction) => Ok(LineContent::Instruction(instruction.into_owned())),
+            Err(_) if open_macro.is_some() && clean.contains('\\') => Ok(LineContent::MacroBody),
+            Err(error) => match parse_loose_call(clean) {
+                Some(call) => Ok(LineContent::Instruction(Instruction::Directive(call))),
+                None => Err(error),
+            },
+        },
+    }
+}
+
+/// How deeply macro calls may nest before the expansion is assumed to be recursive.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+struct LexState<'a> {
+    tokens: Vec<Token<'a>>,
+    errors: Vec<AssembleError>,
+    /// The chain of files currently being read, outermost first.
+    includes: Vec<Include>,
+    macros: HashMap<String, Rc<Macro>>,
+    /// The number of macro expansions so far, which keeps local labels unique.
+    expansions: usize,
+}
+
+struct Include {
+    name: String,
+    path: PathBuf,
+}
+
+impl Include {
+    fn new(path: &Path) -> Include {
+        Include {
+            name: path.to_string_lossy().into_owned(),
+            path: fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+        }
+    }
+}
+
+/// One source line awaiting tokenization. The source is borrowed when it is
+/// part of the input being lexed, and owned when it was read from an
+/// included file or produced by expanding a macro.
+struct Line<'a> {
+    file: Rc<str>,
+    number: usize,
+    source: Cow<'a, str>,
+    /// Where the instruction is in `source`, without whitespace or comments.
+    code: Range<usize>,
+    expansion: Option<Rc<Expansion>>,
+}
+
+impl<'a> Line<'a> {
+    /// Line `number` of `file`, or `None` if it holds no code.
+    fn new(
+        file: &Rc<str>,
+        number: usize,
+        source: Cow<'a, str>,
+        expansion: Option<Rc<Expansion>>,
+    ) -> Option<Line<'a>> {
+        let code = split_comment(&source).0;
+        let start = code.len() - code.trim_start().len();
+        let end = code.trim_end().len();
+        if start >= end {
+            return None;
+        }
+        Some(Line {
+            file: Rc::clone(file),
+            number,
+            code: start..end,
+            source,
+            expansion,
+        })
+    }
+
+    /// The instruction without whitespace or comments.
+    fn clean(&self) -> &str {
+        &self.source[self.code.clone()]
+    }
+
+    fn column(&self) -> usize {
+        self.source[..self.code.start].chars().count() + 1
+    }
+
+    /// Parses the instruction, borrowing its names from the input when the
+    /// line is part of it.
+    fn instruction(&self) -> Result<Instruction<'a>, LineError> {
+        match &self.source {
+            Cow::Borrowed(source) => {
+                let source: &'a str = source;
+                parse_instruction(&source[self.code.clone()])
+            }
+            Cow::Owned(source) => {
+                parse_instruction(&source[self.code.clone()]).map(Instruction::into_owned)
+            }
+        }
+    }
+
+    fn token(&self, instruction: Instruction<'a>) -> Token<'a> {
+        Token {
+            token: instruction,
+            file: Rc::clone(&self.file),
+            line: self.number,
+            column: self.column(),
+            width: self.clean().chars().count(),
+            source: self.source.clone(),
+            expansion: self.expansion.clone(),
+        }
+    }
+
+    fn error(&self, error: LineError) -> AssembleError {
+        let clean = self.clean();
+        let offset = clean[..error.offset].chars().count();
+        let width = clean[error.offset..error.offset + error.width]
+            .chars()
+            .count();
+        let error = AssembleError::new(
+            error.message,
+            &self.file,
+            self.number,
+            self.column() + offset,
+            width,
+            &self.source,
+        );
+        match &self.expansion {
+            Some(expansion) => expansion.annotate(error),
+            None => error,
+        }
+    }
+}
+
+impl<'a> LexState<'a> {
+    fn lex(&mut self, lines: impl Iterator<Item = Cow<'a, str>>, file_name: &str) {
+        let file: Rc<str> = Rc::from(file_name);
+        let mut lines = lines.enumerate();
+        while let Some((index, source)) = lines.next() {
+            let line = match Line::new(&file, index + 1, source, None) {
+                Some(line) => line,
                 None => continue,
             };
+            if directive_name(line.clean()) == ".macro" {
+                self.define_macro(&line, &mut lines);
+            } else {
+                self.lex_line(&line);
+            }
         }
-        Lexer { tokens }
     }
 
-    pub fn get_tokens(&self) -> &Vec<Token> {
-        &self.tokens
+    fn lex_line(&mut self, line: &Line<'a>) {
+        if let Some(definition) = self.macros.get(first_word(line.clean())) {
+            let definition = Rc::clone(definition);
+            match parse_macro_call(line.clean(), &definition) {
+                Ok((name, args)) => {
+                    let token = line.token(Instruction::Directive(Directive::MacroCall {
+                        name,
+                        args: args.clone(),
+                    }));
+                    self.tokens.push(token);
+                    self.expand(&definition, &args);
+                }
+                Err(error) => self.errors.push(line.error(error)),
+            }
+            return;
+        }
+
+        match line.instruction() {
+            Ok(instruction) => {
+                let token = line.token(instruction);
+                let include = match token.get_token() {
+                    Instruction::Directive(Directive::Include(path)) => Some(path.clone()),
+                    _ => None,
+                };
+                self.tokens.push(token);
+                if let Some(path) = include {
+                    self.include(&path);
+                }
+            }
+            Err(error) => self.errors.push(line.error(error)),
+        }
+    }
+
+    /// Records the macro whose `.macro` line is `line`, consuming its body
+    /// up to and including `.endm` from `lines`.
+    fn define_macro(
+        &mut self,
+        line: &Line,
+        lines: &mut impl Iterator<Item = (usize, Cow<'a, str>)>,
+    ) {
+        let mut definition = match parse_macro_header(line.clean()) {
+            Ok((name, params)) => Macro::new(name, params, Rc::clone(&line.file), line.number),
+            Err(error) => {
+                self.errors.push(line.error(error));
+                Macro::new(String::new(), Vec::new(), Rc::clone(&line.file), line.number)
+            }
+        };
+
+        let mut closed = false;
+        for (index, source) in lines {
+            let body = match Line::new(&line.file, index + 1, source, None) {
+                Some(body) => body,
+                None => continue,
+            };
+            match directive_name(body.clean()) {
+                ".endm" => {
+                    closed = true;
+                    break;
+                }
+                ".macro" => {
+                    let message = String::from("macros cannot be defined inside another macro");
+                    self.errors.push(body.error(LineError::new(0, 6, message)));
+                }
+                _ => definition.push_line(body.number, &body.source),
+            }
+        }
+
+        if !closed {
+            let message = format!("macro '{}' has no matching '.endm'", definition.name);
+            let width = line.clean().len();
+            self.errors.push(line.error(LineError::new(0, width, message)));
+        } else if let Some(previous) = self.macros.get(&definition.name) {
+            let message = format!("macro '{}' is already defined", definition.name);
+            let width = line.clean().len();
+            let note = format!("first defined at {}:{}", previous.file, previous.line);
+            self.errors.push(line.error(LineError::new(0, width, message)).with_note(note));
+        } else if !definition.name.is_empty() {
+            self.macros.insert(definition.name.clone(), Rc::new(definition));
+        }
+    }
+
+    /// Lexes the body of `definition` for the macro call token just pushed.
+    fn expand(&mut self, definition: &Macro, args: &[String]) {
+        let call = self.tokens.last().unwrap();
+        let expansion = Rc::new(Expansion {
+            macro_name: definition.name.clone(),
+            file: Rc::clone(&call.file),
+            line: call.line,
+            column: call.column,
+            parent: call.expansion.clone(),
+        });
+        if expansion.depth() > MAX_EXPANSION_DEPTH {
+            let message = format!(
+                "macro calls nested more than {} deep; is '{}' recursive?",
+                MAX_EXPANSION_DEPTH, definition.name
+            );
+            // Only the outermost call is useful; the rest repeat the same cycle
+            let outermost = expansion.outermost();
+            let error = AssembleError::new(
+                message,
+                &call.file,
+                call.line,
+                call.column,
+                call.width(),
+                &call.source,
+            );
+            self.errors.push(outermost.note(error));
+            return;
+        }
+
+        self.expansions += 1;
+        let id = self.expansions;
+        for (number, source) in &definition.body {
+            let source = Cow::Owned(definition.expand_line(source, args, id));
+            let expansion = Some(Rc::clone(&expansion));
+            if let Some(line) = Line::new(&definition.file, *number, source, expansion) {
+                self.lex_line(&line);
+            }
+        }
+    }
+
+    /// Puts instructions that store the values of every `.data` directive in
+    /// front of the program, attributed to the directive they come from.
+    fn data_prologue(&mut self) {
+        let mut prologue = Vec::new();
+        for token in &self.tokens {
+            let (name, values) = match token.get_token() {
+                Instruction::Directive(Directive::Data { name, values }) => (name, values),
+                _ => continue,
+            };
+            for (offset, value) in values.iter().enumerate() {
+                let address = match offset {
+                    0 => AValue::Symbol(Cow::Owned(name.clone())),
+                    _ => AValue::Expression(Expr::Binary(
+                        BinaryOp::Add,
+                        Box::new(Expr::Symbol(name.clone())),
+                        Box::new(Expr::Number(offset as i32)),
+                    )),
+                };
+                let store = |comp| Instruction::CInstruction {
+                    dest: Dest::M,
+                    comp,
+                    jump: Jump::Null,
+                };
+                let load = |dest, comp| Instruction::CInstruction {
+                    dest,
+                    comp,
+                    jump: Jump::Null,
+                };
+                // 0, 1 and -1 can be stored directly, anything else goes through D
+                let instructions = match *value {
+                    0 => vec![Instruction::AInstruction(address), store(Comp::Zero)],
+                    1 => vec![Instruction::AInstruction(address), store(Comp::One)],
+                    0xffff => vec![Instruction::AInstruction(address), store(Comp::MinusOne)],
+                    value if value <= MAX_ADDRESS => vec![
+                        Instruction::AInstruction(AValue::Literal(value)),
+                        load(Dest::D, Comp::A),
+                        Instruction::AInstruction(address),
+                        store(Comp::D),
+                    ],
+                    value => vec![
+                        Instruction::AInstruction(AValue::Literal(!value)),
+                        load(Dest::D, Comp::NotA),
+                        Instruction::AInstruction(address),
+                        store(Comp::D),
+                    ],
+                };
+                prologue.extend(instructions.into_iter().map(|instruction| Token {
+                    token: instruction,
+                    ..token.clone()
+                }));
+            }
+        }
+        self.tokens.splice(0..0, prologue);
+    }
+
+    /// Splices in the file named by the `.include` token just pushed, resolving
+    /// `path` relative to the including file.
+    fn include(&mut self, path: &str) {
+        let including = self.includes.last().unwrap();
+        let base = Path::new(&including.name).parent().unwrap_or_else(|| Path::new(""));
+        let include = Include::new(&base.join(path));
+
+        let directive = self.tokens.last().unwrap();
+        if self.includes.iter().any(|outer| outer.path == include.path) {
+            let chain: Vec<&str> = self
+                .includes
+                .iter()
+                .map(|outer| outer.name.as_str())
+                .chain(std::iter::once(include.name.as_str()))
+                .collect();
+            let message = format!("include cycle: {}", chain.join(" -> "));
+            self.errors.push(directive.error(message, 0, directive.width()));
+            return;
+        }
+        let code = match fs::read_to_string(&include.name) {
+            Ok(code) => code,
+            Err(error) => {
+                let message = format!("could not include {}: {}", include.name, error);
+                self.errors.push(directive.error(message, 0, directive.width()));
+                return;
+            }
+        };
+
+        let name = include.name.clone();
+        self.includes.push(include);
+        // The file is dropped once read, so the tokens keep copies of its lines
+        let lines = split_lines(&code).map(|line| Cow::Owned(String::from(line)));
+        self.lex(lines, &name);
+        self.includes.pop();
     }
 }
 
-#[derive(Debug)]
-pub struct Token {
-    token: Instruction,
+/// An instruction together with where it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    token: Instruction<'a>,
+    file: Rc<str>,
+    line: usize,
+    column: usize,
+    width: usize,
+    source: Cow<'a, str>,
+    expansion: Option<Rc<Expansion>>,
 }
 
-#[derive(Debug)]
-pub enum Instruction {
-    LInstruction(String),
-    AInstruction(String),
-    CInstruction(String),
+/// The macro call a token was expanded from. For such tokens the file, line
+/// and source are those of the line in the macro body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub macro_name: String,
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+    /// The expansion the call itself was written in, for nested macros.
+    pub parent: Option<Rc<Expansion>>,
+}
+
+impl Expansion {
+    fn depth(&self) -> usize {
+        1 + self.parent.as_ref().map_or(0, |parent| parent.depth())
+    }
+
+    fn outermost(&self) -> &Expansion {
+        self.parent.as_ref().map_or(self, |parent| parent.outermost())
+    }
+
+    fn note(&self, error: AssembleError) -> AssembleError {
+        error.with_note(format!(
+            "in expansion of macro '{}' at {}:{}:{}",
+            self.macro_name, self.file, self.line, self.column
+        ))
+    }
+
+    /// Adds a note for every call in the chain, innermost first.
+    fn annotate(&self, error: AssembleError) -> AssembleError {
+        let error = self.note(error);
+        match &self.parent {
+            Some(parent) => parent.annotate(error),
+            None => error,
+        }
+    }
 }
 
+/// An instruction, with its label or symbol borrowed from the source when
+/// possible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction<'a> {
+    LInstruction(Cow<'a, str>),
+    AInstruction(AValue<'a>),
+    CInstruction { dest: Dest, comp: Comp, jump: Jump },
+    /// An assembler directive, which emits no machine code itself.
+    Directive(Directive),
+}
 
-impl Token {
-    fn new(clean_line: String) -> Token {
-        if clean_line.starts_with("@") {
-            Token {
-                token: Instruction::AInstruction(String::from(clean_line.trim())),
+impl Instruction<'_> {
+    /// The instruction with its names copied, so it outlives the source.
+    pub fn into_owned(self) -> Instruction<'static> {
+        match self {
+            Instruction::LInstruction(label) => {
+                Instruction::LInstruction(Cow::Owned(label.into_owned()))
             }
-        } else if clean_line.starts_with("(") {
-            Token {
-                token: Instruction::LInstruction(String::from(clean_line.trim()))
+            Instruction::AInstruction(value) => Instruction::AInstruction(value.into_owned()),
+            Instruction::CInstruction { dest, comp, jump } => {
+                Instruction::CInstruction { dest, comp, jump }
             }
-        } else {
-            Token {
-                token: Instruction::CInstruction(String::from(clean_line.trim()))
+            Instruction::Directive(directive) => Instruction::Directive(directive),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    /// `.equ NAME value` or `.define NAME value`: an assembler-time constant.
+    Constant { name: String, value: Expr },
+    /// `.include "file.asm"`: the tokens of the named file follow this one.
+    Include(String),
+    /// A call of a macro defined with `.macro`: the tokens of its expanded
+    /// body follow this one.
+    MacroCall { name: String, args: Vec<String> },
+    /// `.global NAME`: exports a label to other modules when linking.
+    Global(String),
+    /// `.extern NAME`: a label defined in another module.
+    Extern(String),
+    /// `.var NAME [size]`: reserves `size` words of RAM, one by default.
+    Var { name: String, size: Expr },
+    /// `.data NAME value, ...`: reserves a word of RAM per value. The lexer
+    /// emits a prologue storing the values ahead of the program.
+    Data { name: String, values: Vec<u16> },
+}
+
+/// The operand of an A-instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AValue<'a> {
+    Literal(u16),
+    Symbol(Cow<'a, str>),
+    /// Any other constant expression, evaluated once all labels are known.
+    Expression(Expr),
+}
+
+impl AValue<'_> {
+    /// The operand with its symbol copied, so it outlives the source.
+    pub fn into_owned(self) -> AValue<'static> {
+        match self {
+            AValue::Literal(value) => AValue::Literal(value),
+            AValue::Symbol(name) => AValue::Symbol(Cow::Owned(name.into_owned())),
+            AValue::Expression(expr) => AValue::Expression(expr),
+        }
+    }
+}
+
+impl fmt::Display for Instruction<'_> {
+    /// Writes the instruction in canonical Hack assembly syntax.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::LInstruction(label) => write!(f, "({})", label),
+            Instruction::AInstruction(value) => write!(f, "@{}", value),
+            Instruction::CInstruction { dest, comp, jump } => {
+                if *dest != Dest::Null {
+                    write!(f, "{}=", dest.mnemonic())?;
+                }
+                write!(f, "{}", comp.mnemonic())?;
+                if *jump != Jump::Null {
+                    write!(f, ";{}", jump.mnemonic())?;
+                }
+                Ok(())
             }
+            Instruction::Directive(directive) => write!(f, "{}", directive),
+        }
+    }
+}
+
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Directive::Constant { name, value } => write!(f, ".equ {} {}", name, value),
+            Directive::Include(path) => write!(f, ".include \"{}\"", path),
+            Directive::MacroCall { name, args } if args.is_empty() => write!(f, "{}", name),
+            Directive::MacroCall { name, args } => write!(f, "{} {}", name, args.join(", ")),
+            Directive::Global(name) => write!(f, ".global {}", name),
+            Directive::Extern(name) => write!(f, ".extern {}", name),
+            Directive::Var { name, size } => write!(f, ".var {} {}", name, size),
+            Directive::Data { name, values } => {
+                let values: Vec<String> = values.iter().map(u16::to_string).collect();
+                write!(f, ".data {} {}", name, values.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for AValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AValue::Literal(value) => write!(f, "{}", value),
+            AValue::Symbol(symbol) => write!(f, "{}", symbol),
+            AValue::Expression(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dest {
+    Null,
+    M,
+    D,
+    MD,
+    A,
+    AM,
+    AD,
+    AMD,
+}
+
+impl Dest {
+    pub const ALL: [Dest; 8] = [
+        Dest::Null,
+        Dest::M,
+        Dest::D,
+        Dest::MD,
+        Dest::A,
+        Dest::AM,
+        Dest::AD,
+        Dest::AMD,
+    ];
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Dest> {
+        let dest = match mnemonic.as_bytes() {
+            b"M" => Dest::M,
+            b"D" => Dest::D,
+            b"MD" => Dest::MD,
+            b"A" => Dest::A,
+            b"AM" => Dest::AM,
+            b"AD" => Dest::AD,
+            b"AMD" => Dest::AMD,
+            _ => return None,
+        };
+        Some(dest)
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Dest::Null => "null",
+            Dest::M => "M",
+            Dest::D => "D",
+            Dest::MD => "MD",
+            Dest::A => "A",
+            Dest::AM => "AM",
+            Dest::AD => "AD",
+            Dest::AMD => "AMD",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comp {
+    Zero,
+    One,
+    MinusOne,
+    D,
+    A,
+    NotD,
+    NotA,
+    NegD,
+    NegA,
+    DPlusOne,
+    APlusOne,
+    DMinusOne,
+    AMinusOne,
+    DPlusA,
+    DMinusA,
+    AMinusD,
+    DAndA,
+    DOrA,
+    M,
+    NotM,
+    NegM,
+    MPlusOne,
+    MMinusOne,
+    DPlusM,
+    DMinusM,
+    MMinusD,
+    DAndM,
+    DOrM,
+    /// A comp outside the standard set, such as the shift `D<<`. Whether it
+    /// can be encoded depends on the instruction set the parser is given.
+    Extended(Mnemonic),
+}
+
+impl Comp {
+    /// Every comp of the standard Hack instruction set.
+    pub const STANDARD: [Comp; 28] = [
+        Comp::Zero,
+        Comp::One,
+        Comp::MinusOne,
+        Comp::D,
+        Comp::A,
+        Comp::NotD,
+        Comp::NotA,
+        Comp::NegD,
+        Comp::NegA,
+        Comp::DPlusOne,
+        Comp::APlusOne,
+        Comp::DMinusOne,
+        Comp::AMinusOne,
+        Comp::DPlusA,
+        Comp::DMinusA,
+        Comp::AMinusD,
+        Comp::DAndA,
+        Comp::DOrA,
+        Comp::M,
+        Comp::NotM,
+        Comp::NegM,
+        Comp::MPlusOne,
+        Comp::MMinusOne,
+        Comp::DPlusM,
+        Comp::DMinusM,
+        Comp::MMinusD,
+        Comp::DAndM,
+        Comp::DOrM,
+    ];
+
+    /// Looks up a comp mnemonic. Besides the canonical forms this accepts the
+    /// commutative spellings of `+`, `&` and `|`, such as `A+D` or `1+M`.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Comp> {
+        let comp = match mnemonic.as_bytes() {
+            b"0" => Comp::Zero,
+            b"1" => Comp::One,
+            b"-1" => Comp::MinusOne,
+            b"D" => Comp::D,
+            b"A" => Comp::A,
+            b"!D" => Comp::NotD,
+            b"!A" => Comp::NotA,
+            b"-D" => Comp::NegD,
+            b"-A" => Comp::NegA,
+            b"D+1" | b"1+D" => Comp::DPlusOne,
+            b"A+1" | b"1+A" => Comp::APlusOne,
+            b"D-1" => Comp::DMinusOne,
+            b"A-1" => Comp::AMinusOne,
+            b"D+A" | b"A+D" => Comp::DPlusA,
+            b"D-A" => Comp::DMinusA,
+            b"A-D" => Comp::AMinusD,
+            b"D&A" | b"A&D" => Comp::DAndA,
+            b"D|A" | b"A|D" => Comp::DOrA,
+            b"M" => Comp::M,
+            b"!M" => Comp::NotM,
+            b"-M" => Comp::NegM,
+            b"M+1" | b"1+M" => Comp::MPlusOne,
+            b"M-1" => Comp::MMinusOne,
+            b"D+M" | b"M+D" => Comp::DPlusM,
+            b"D-M" => Comp::DMinusM,
+            b"M-D" => Comp::MMinusD,
+            b"D&M" | b"M&D" => Comp::DAndM,
+            b"D|M" | b"M|D" => Comp::DOrM,
+            _ => return None,
+        };
+        Some(comp)
+    }
+
+    /// An extended comp spelled `mnemonic`, if it could be one: up to
+    /// [`MAX_MNEMONIC`] registers, digits and operators, which is not a
+    /// standard comp.
+    pub fn extended(mnemonic: &str) -> Option<Comp> {
+        let valid = |c: char| matches!(c, 'A' | 'D' | 'M' | '0'..='9' | '+' | '-' | '*' | '/')
+            || "%&|^!~<>".contains(c);
+        if Comp::from_mnemonic(mnemonic).is_some()
+            || mnemonic.is_empty()
+            || mnemonic.len() > MAX_MNEMONIC
+            || !mnemonic.chars().all(valid)
+        {
+            return None;
+        }
+        let mut bytes = [0; MAX_MNEMONIC];
+        bytes[..mnemonic.len()].copy_from_slice(mnemonic.as_bytes());
+        Some(Comp::Extended(Mnemonic {
+            bytes,
+            len: mnemonic.len() as u8,
+        }))
+    }
+
+    pub fn mnemonic(&self) -> &str {
+        match self {
+            Comp::Zero => "0",
+            Comp::One => "1",
+            Comp::MinusOne => "-1",
+            Comp::D => "D",
+            Comp::A => "A",
+            Comp::NotD => "!D",
+            Comp::NotA => "!A",
+            Comp::NegD => "-D",
+            Comp::NegA => "-A",
+            Comp::DPlusOne => "D+1",
+            Comp::APlusOne => "A+1",
+            Comp::DMinusOne => "D-1",
+            Comp::AMinusOne => "A-1",
+            Comp::DPlusA => "D+A",
+            Comp::DMinusA => "D-A",
+            Comp::AMinusD => "A-D",
+            Comp::DAndA => "D&A",
+            Comp::DOrA => "D|A",
+            Comp::M => "M",
+            Comp::NotM => "!M",
+            Comp::NegM => "-M",
+            Comp::MPlusOne => "M+1",
+            Comp::MMinusOne => "M-1",
+            Comp::DPlusM => "D+M",
+            Comp::DMinusM => "D-M",
+            Comp::MMinusD => "M-D",
+            Comp::DAndM => "D&M",
+            Comp::DOrM => "D|M",
+            Comp::Extended(mnemonic) => mnemonic.as_str(),
+        }
+    }
+}
+
+/// The longest spelling of an extended comp.
+pub const MAX_MNEMONIC: usize = 8;
+
+/// The spelling of an extended comp, stored inline so `Comp` stays `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mnemonic {
+    bytes: [u8; MAX_MNEMONIC],
+    len: u8,
+}
+
+impl Mnemonic {
+    pub fn as_str(&self) -> &str {
+        // Only built from ASCII by `Comp::extended`
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+}
+
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Jump {
+    Null,
+    JGT,
+    JEQ,
+    JGE,
+    JLT,
+    JNE,
+    JLE,
+    JMP,
+}
+
+impl Jump {
+    pub const ALL: [Jump; 8] = [
+        Jump::Null,
+        Jump::JGT,
+        Jump::JEQ,
+        Jump::JGE,
+        Jump::JLT,
+        Jump::JNE,
+        Jump::JLE,
+        Jump::JMP,
+    ];
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Jump> {
+        let jump = match mnemonic.as_bytes() {
+            b"JGT" => Jump::JGT,
+            b"JEQ" => Jump::JEQ,
+            b"JGE" => Jump::JGE,
+            b"JLT" => Jump::JLT,
+            b"JNE" => Jump::JNE,
+            b"JLE" => Jump::JLE,
+            b"JMP" => Jump::JMP,
+            _ => return None,
+        };
+        Some(jump)
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Jump::Null => "null",
+            Jump::JGT => "JGT",
+            Jump::JEQ => "JEQ",
+            Jump::JGE => "JGE",
+            Jump::JLT => "JLT",
+            Jump::JNE => "JNE",
+            Jump::JLE => "JLE",
+            Jump::JMP => "JMP",
         }
     }
+}
 
-    pub fn get_token(&self) -> &Instruction {
+impl<'a> Token<'a> {
+    pub fn get_token(&self) -> &Instruction<'a> {
         &self.token
     }
+
+    /// The token with its source and names copied, so it outlives the
+    /// code it was lexed from.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            token: self.token.into_owned(),
+            file: self.file,
+            line: self.line,
+            column: self.column,
+            width: self.width,
+            source: Cow::Owned(self.source.into_owned()),
+            expansion: self.expansion,
+        }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// The 1-based line number of the instruction.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The 1-based column where the instruction starts.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The number of characters the instruction spans, excluding comments.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The full source line the instruction was read from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The offset from `column` and width of the comp field of a
+    /// C-instruction, in characters, for pointing errors at it.
+    pub(crate) fn comp_span(&self) -> (usize, usize) {
+        let code: String = self
+            .source
+            .chars()
+            .skip(self.column - 1)
+            .take(self.width)
+            .collect();
+        let start = code.find('=').map_or(0, |index| index + 1);
+        let end = code[start..].find(';').map_or(code.len(), |index| start + index);
+        let field = Field::new(&code, start, end);
+        (
+            code[..field.offset].chars().count(),
+            field.text.chars().count(),
+        )
+    }
+
+    /// Whether the instruction is encoded to a word of machine code, as
+    /// opposed to a label or directive.
+    pub fn emits_word(&self) -> bool {
+        matches!(
+            self.token,
+            Instruction::AInstruction(_) | Instruction::CInstruction { .. }
+        )
+    }
+
+    /// The macro call the instruction was expanded from, if any.
+    pub fn expansion(&self) -> Option<&Expansion> {
+        self.expansion.as_deref()
+    }
+
+    /// Builds an error underlining `width` characters starting `offset`
+    /// characters into the instruction.
+    pub fn error(&self, message: String, offset: usize, width: usize) -> AssembleError {
+        let error = AssembleError::new(
+            message,
+            &self.file,
+            self.line,
+            self.column + offset,
+            width,
+            &self.source,
+        );
+        match &self.expansion {
+            Some(expansion) => expansion.annotate(error),
+            None => error,
+        }
+    }
 }
 
+fn parse_instruction(line: &str) -> Result<Instruction<'_>, LineError> {
+    let line = line.trim_end();
+    if line.starts_with('@') {
+        let value = Field::new(line, 1, line.len());
+        Ok(Instruction::AInstruction(parse_a_value(value.text, value.offset)?))
+    } else if line.starts_with('(') {
+        if !line.ends_with(')') {
+            return Err(LineError::new(
+                0,
+                line.len(),
+                format!("missing ')' in label declaration '{}'", line),
+            ));
+        }
+        let label = Field::new(line, 1, line.len() - 1);
+        if !is_symbol(label.text) {
+            return Err(label.error(format!("invalid label name '{}'", label.text)));
+        }
+        Ok(Instruction::LInstruction(Cow::Borrowed(label.text)))
+    } else if line.starts_with('.') {
+        parse_directive(line).map(Instruction::Directive)
+    } else {
+        parse_c_instruction(line)
+    }
+}
 
-fn preprocess_code(line: String) -> Option<String> {
-    let trimmed_line = line.trim();
+fn parse_directive(line: &str) -> Result<Directive, LineError> {
+    let name_end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let name = &line[..name_end];
+    let arguments = Field::new(line, name_end, line.len());
+    match name {
+        ".equ" | ".define" => {
+            let symbol_end = arguments
+                .text
+                .find(char::is_whitespace)
+                .unwrap_or(arguments.text.len());
+            let symbol = &arguments.text[..symbol_end];
+            if !is_symbol(symbol) {
+                return Err(LineError::new(
+                    arguments.offset,
+                    symbol.len(),
+                    format!("expected '{} NAME value'", name),
+                ));
+            }
+            let value = Field::new(line, arguments.offset + symbol_end, line.len());
+            if value.text.is_empty() {
+                return Err(value.error(format!("missing value for '{}'", symbol)));
+            }
+            let value = Expr::parse(value.text).map_err(|error| {
+                LineError::new(value.offset + error.offset, error.width, error.message)
+            })?;
+            Ok(Directive::Constant {
+                name: String::from(symbol),
+                value,
+            })
+        }
+        ".include" => {
+            let path = arguments
+                .text
+                .strip_prefix('"')
+                .and_then(|path| path.strip_suffix('"'))
+                .filter(|path| !path.is_empty())
+                .ok_or_else(|| arguments.error(String::from("expected '.include \"file.asm\"'")))?;
+            Ok(Directive::Include(String::from(path)))
+        }
+        ".global" | ".extern" => {
+            if !is_symbol(arguments.text) {
+                return Err(arguments.error(format!("expected '{} NAME'", name)));
+            }
+            let symbol = String::from(arguments.text);
+            Ok(if name == ".global" {
+                Directive::Global(symbol)
+            } else {
+                Directive::Extern(symbol)
+            })
+        }
+        ".var" | ".data" => {
+            let symbol_end = arguments
+                .text
+                .find(char::is_whitespace)
+                .unwrap_or(arguments.text.len());
+            let symbol = &arguments.text[..symbol_end];
+            if !is_symbol(symbol) {
+                return Err(LineError::new(
+                    arguments.offset,
+                    symbol.len(),
+                    format!("expected a variable name after '{}'", name),
+                ));
+            }
+            let value = Field::new(line, arguments.offset + symbol_end, line.len());
+            if name == ".var" {
+                let size = if value.text.is_empty() {
+                    Expr::Number(1)
+                } else {
+                    Expr::parse(value.text).map_err(|error| {
+                        LineError::new(value.offset + error.offset, error.width, error.message)
+                    })?
+                };
+                return Ok(Directive::Var {
+                    name: String::from(symbol),
+                    size,
+                });
+            }
 
-    return if trimmed_line.contains("//") {
-        if trimmed_line.starts_with("//") {
-            None
-        } else {
-            let comment_index = trimmed_line
-                .chars()
-                .position(|x| x == '/')
-                .unwrap();
+            if value.text.is_empty() {
+                return Err(value.error(format!("missing values for '{}'", symbol)));
+            }
+            let mut values = Vec::new();
+            let mut start = value.offset;
+            for part in line[value.offset..].split(',') {
+                let field = Field::new(line, start, start + part.len());
+                start += part.len() + 1;
+                values.push(parse_data_value(field)?);
+            }
+            Ok(Directive::Data {
+                name: String::from(symbol),
+                values,
+            })
+        }
+        ".endm" => Err(LineError::new(
+            0,
+            name.len(),
+            String::from("'.endm' without a matching '.macro'"),
+        )),
+        _ => Err(LineError::new(0, name.len(), format!("unknown directive '{}'", name))),
+    }
+}
+
+/// Evaluates one `.data` value, which must be a number from -32768 to 65535;
+/// negative values are stored in two's complement.
+fn parse_data_value(field: Field) -> Result<u16, LineError> {
+    if field.text.is_empty() {
+        return Err(field.error(String::from("missing value")));
+    }
+    let expr = Expr::parse(field.text).map_err(|error| {
+        LineError::new(field.offset + error.offset, error.width, error.message)
+    })?;
+    let value = expr
+        .eval(&|_| None)
+        .map_err(|_| field.error(String::from("'.data' values must be numbers")))?;
+    if (-32768..=65535).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(field.error(format!("'{}' does not fit in 16 bits", field.text)))
+    }
+}
 
-            let line_no_comment = trimmed_line
-                .chars()
-                .take(comment_index - 1)
-                .collect::<String>();
+/// The directive a line starts with, or `""` if it is not a directive.
+fn directive_name(line: &str) -> &str {
+    match first_word(line) {
+        word if word.starts_with('.') => word,
+        _ => "",
+    }
+}
 
-            Some(line_no_comment)
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// Parses `.macro NAME param, ...` into the macro name and its parameters.
+fn parse_macro_header(line: &str) -> Result<(String, Vec<String>), LineError> {
+    let arguments = Field::new(line, ".macro".len(), line.len());
+    let name_end = arguments
+        .text
+        .find(char::is_whitespace)
+        .unwrap_or(arguments.text.len());
+    let name = &arguments.text[..name_end];
+    if !is_symbol(name) {
+        return Err(LineError::new(
+            arguments.offset,
+            name.len(),
+            String::from("expected '.macro NAME param, ...'"),
+        ));
+    }
+    if Comp::from_mnemonic(name).is_some() || Dest::from_mnemonic(name).is_some() {
+        return Err(LineError::new(
+            arguments.offset,
+            name.len(),
+            format!("macro name '{}' would hide an instruction", name),
+        ));
+    }
+
+    let params = Field::new(line, arguments.offset + name_end, line.len());
+    let mut names: Vec<String> = Vec::new();
+    for param in params.text.split(',').map(str::trim) {
+        if param.is_empty() && params.text.is_empty() {
+            break;
+        }
+        if !is_symbol(param) {
+            return Err(params.error(format!("invalid macro parameter '{}'", param)));
+        }
+        if names.iter().any(|known| known == param) {
+            return Err(params.error(format!("duplicate macro parameter '{}'", param)));
         }
+        names.push(String::from(param));
+    }
+    Ok((String::from(name), names))
+}
+
+/// Parses `NAME arg, ...`, checking the arguments against `definition`.
+fn parse_macro_call(line: &str, definition: &Macro) -> Result<(String, Vec<String>), LineError> {
+    let name = first_word(line);
+    let arguments = Field::new(line, name.len(), line.len());
+    let args: Vec<String> = if arguments.text.is_empty() {
+        Vec::new()
+    } else {
+        arguments
+            .text
+            .split(',')
+            .map(|arg| String::from(arg.trim()))
+            .collect()
+    };
+    if args.iter().any(String::is_empty) {
+        return Err(arguments.error(String::from("empty macro argument")));
+    }
+    if args.len() != definition.params.len() {
+        return Err(LineError::new(
+            0,
+            line.len(),
+            format!(
+                "macro '{}' takes {} argument(s) but {} were given",
+                name,
+                definition.params.len(),
+                args.len()
+            ),
+        ));
+    }
+    Ok((String::from(name), args))
+}
+
+/// Parses `NAME arg, ...` as a call of a macro that may not be known yet.
+fn parse_loose_call(line: &str) -> Option<Directive> {
+    let name = first_word(line);
+    if !is_symbol(name) {
+        return None;
+    }
+    let arguments = line[name.len()..].trim();
+    let args = if arguments.is_empty() {
+        Vec::new()
+    } else {
+        arguments.split(',').map(|arg| String::from(arg.trim())).collect()
+    };
+    Some(Directive::MacroCall {
+        name: String::from(name),
+        args,
+    })
+}
+
+/// A lexing failure within a single line, with `offset` and `width` in bytes
+/// relative to the start of the instruction.
+struct LineError {
+    offset: usize,
+    width: usize,
+    message: String,
+}
+
+impl LineError {
+    fn new(offset: usize, width: usize, message: String) -> LineError {
+        LineError { offset, width, message }
+    }
+}
+
+fn parse_a_value(value: &str, offset: usize) -> Result<AValue<'_>, LineError> {
+    if value.bytes().all(|c| c.is_ascii_digit()) && !value.is_empty() {
+        value
+            .parse::<u16>()
+            .ok()
+            .filter(|value| *value <= MAX_ADDRESS)
+            .map(AValue::Literal)
+            .ok_or_else(|| {
+                LineError::new(
+                    offset,
+                    value.len(),
+                    format!(
+                        "numeric constant '{}' does not fit in 15 bits (0..={})",
+                        value, MAX_ADDRESS
+                    ),
+                )
+            })
+    } else if is_symbol(value) {
+        Ok(AValue::Symbol(Cow::Borrowed(value)))
     } else {
-        if trimmed_line.is_empty() {
-            None
-        } else { Some(String::from(trimmed_line)) }
+        Expr::parse(value)
+            .map(AValue::Expression)
+            .map_err(|error| LineError::new(offset + error.offset, error.width, error.message))
+    }
+}
+
+/// Splits `dest=comp;jump` into its fields, where both `dest=` and `;jump` are
+/// optional. Whitespace is allowed anywhere inside the instruction.
+fn parse_c_instruction(line: &str) -> Result<Instruction<'_>, LineError> {
+    let (dest, comp_start) = match line.find('=') {
+        Some(index) => (Some(Field::new(line, 0, index)), index + 1),
+        None => (None, 0),
+    };
+    let (comp, jump) = match line[comp_start..].find(';') {
+        Some(index) => (
+            Field::new(line, comp_start, comp_start + index),
+            Some(Field::new(line, comp_start + index + 1, line.len())),
+        ),
+        None => (Field::new(line, comp_start, line.len()), None),
+    };
+
+    let dest = match dest {
+        Some(dest) => Dest::from_mnemonic(&dest.mnemonic())
+            .ok_or_else(|| dest.error(format!("unknown dest '{}'", dest.text)))?,
+        None => Dest::Null,
+    };
+    let mnemonic = comp.mnemonic();
+    let comp = Comp::from_mnemonic(&mnemonic)
+        .or_else(|| Comp::extended(&mnemonic))
+        .ok_or_else(|| comp.error(format!("unknown comp '{}'", comp.text)))?;
+    let jump = match jump {
+        Some(jump) => Jump::from_mnemonic(&jump.mnemonic())
+            .ok_or_else(|| jump.error(format!("unknown jump '{}'", jump.text)))?,
+        None => Jump::Null,
     };
-}
\ No newline at end of file
+    Ok(Instruction::CInstruction { dest, comp, jump })
+}
+
+/// One field of a C-instruction, trimmed, with its byte offset in the instruction.
+struct Field<'a> {
+    offset: usize,
+    text: &'a str,
+}
+
+impl<'a> Field<'a> {
+    fn new(line: &'a str, start: usize, end: usize) -> Field<'a> {
+        let raw = &line[start..end];
+        let text = raw.trim();
+        Field {
+            offset: start + raw.len() - raw.trim_start().len(),
+            text,
+        }
+    }
+
+    /// The field with all inner whitespace removed, so `D + 1` reads as `D+1`.
+    fn mnemonic(&self) -> Cow<'a, str> {
+        if self.text.contains(char::is_whitespace) {
+            Cow::Owned(self.text.split_whitespace().collect())
+        } else {
+            Cow::Borrowed(self.text)
+        }
+    }
+
+    fn error(&self, message: String) -> LineError {
+        LineError::new(self.offset, self.text.len(), message)
+    }
+}
+
+/// A symbol is a sequence of letters, digits, `_`, `.`, `$` and `:` not starting with a digit.
+pub fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if !first.is_ascii_digit() && is_symbol_char(first) => {
+            chars.all(is_symbol_char)
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':')
+}
+
+/// Splits `line` into its code and the text after `//`, if any. A `//`
+/// inside a quoted `.include` path does not start a comment, and neither
+/// does a single `/`.
+pub(crate) fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quoted = false;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '/' if !quoted && chars.next_if(|(_, next)| *next == '/').is_some() => {
+                return (&line[..index], Some(&line[index + 2..]));
+            }
+            _ => {}
+        }
+    }
+    (line, None)
+}
+
+/// The lines of `code` with their line breaks removed. Besides `\n` and
+/// `\r\n`, a `\r` left at the end of the last line is dropped.
+pub(crate) fn split_lines(code: &str) -> impl Iterator<Item = &str> {
+    code.lines().map(|line| line.strip_suffix('\r').unwrap_or(line))
+}
@@ -1,30 +1,257 @@
-use std::{env, fs};
-use std::io::BufWriter;
-use std::io::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
 
-use lexer::Lexer;
+use error::AssembleError;
+use lexer::{recover_tokens, AValue, Directive, Instruction, Lexer, Token};
+use object::Object;
 use parser::Parser;
+use symbols::Symbol;
 
+pub mod analysis;
+pub mod config;
+pub mod disassembler;
+pub mod error;
+pub mod expr;
+pub mod formatter;
+pub mod isa;
+pub mod json;
 pub mod lexer;
+pub mod linker;
+pub mod lint;
+pub mod listing;
+mod macros;
+pub mod object;
+pub mod optimizer;
+pub mod output;
 pub mod parser;
+pub mod sourcemap;
+pub mod symbols;
 
-pub fn assemble() {
-    let args: Vec<_> = env::args().collect();
-    let file_name = &args[1];
-    let code = fs::read_to_string(file_name)
-        .expect("Could not open file");
-    let binary_file = fs::File::create(
-        file_name.replace(".asm", ".hack"))
-        .unwrap();
-    let lexer = Lexer::new(code);
-    let mut parser = Parser::new();
-
-    parser.first_pass(lexer.get_tokens());
-
-    let mut binary_writer = BufWriter::new(binary_file);
-    let bits = parser.parse(lexer.get_tokens());
-    for bit in bits.into_iter() {
-        if bit == "" { continue; }
-        binary_writer.write(bit.as_bytes()).unwrap();
+/// The result of assembling a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly<'a> {
+    /// The machine words, in ROM order starting at address 0.
+    pub words: Vec<u16>,
+    /// Every symbol known after assembly: predefined symbols, labels and variables.
+    pub symbols: HashMap<String, Symbol>,
+    /// Problems that did not stop assembly.
+    pub warnings: Vec<AssembleError>,
+    /// The source instructions, labels included, in program order.
+    pub tokens: Vec<Token<'a>>,
+}
+
+impl Assembly<'_> {
+    /// The assembly with its tokens copied, so it outlives the source.
+    pub fn into_owned(self) -> Assembly<'static> {
+        Assembly {
+            words: self.words,
+            symbols: self.symbols,
+            warnings: self.warnings,
+            tokens: self.tokens.into_iter().map(Token::into_owned).collect(),
+        }
+    }
+}
+
+/// Assembles Hack source text. `file_name` is only used to label diagnostics.
+pub fn assemble_str<'a>(
+    code: &'a str,
+    file_name: &str,
+) -> Result<Assembly<'a>, Vec<AssembleError>> {
+    assemble_with(code, file_name, Parser::new())
+}
+
+/// Assembles like [`assemble_str`] with a `parser` configured for the target
+/// machine, such as one with a smaller ROM. Every phase runs even when an
+/// earlier one fails, so the errors of all of them are reported together.
+pub fn assemble_with<'a>(
+    code: &'a str,
+    file_name: &str,
+    mut parser: Parser,
+) -> Result<Assembly<'a>, Vec<AssembleError>> {
+    let mut errors = Vec::new();
+    let tokens = lex(code, file_name, &mut errors);
+
+    let words = passes(&mut parser, &tokens, errors, |parser, tokens| parser.parse(tokens))?;
+    Ok(Assembly {
+        words,
+        symbols: parser.get_symbol_table().clone(),
+        warnings: parser.get_warnings().clone(),
+        tokens,
+    })
+}
+
+/// Assembles `code` into a relocatable object for `hack_linker`, returning
+/// it along with any warnings.
+pub fn assemble_object(
+    code: &str,
+    file_name: &str,
+    mut parser: Parser,
+) -> Result<(Object, Vec<AssembleError>), Vec<AssembleError>> {
+    let mut errors = Vec::new();
+    let tokens = lex(code, file_name, &mut errors);
+
+    let object = passes(&mut parser, &tokens, errors, |parser, tokens| {
+        parser.parse_object(tokens)
+    })?;
+    Ok((object, parser.get_warnings().clone()))
+}
+
+/// Assembles `code` like [`assemble_with`], adding the findings of
+/// [`lint::lint`] with `options` to the warnings.
+pub fn lint_with<'a>(
+    code: &'a str,
+    file_name: &str,
+    mut parser: Parser,
+    options: lint::Options,
+) -> Result<Assembly<'a>, Vec<AssembleError>> {
+    let mut errors = Vec::new();
+    let tokens = lex(code, file_name, &mut errors);
+
+    let mut warnings = Vec::new();
+    let words = passes(&mut parser, &tokens, errors, |parser, tokens| {
+        warnings = lint::lint(tokens, parser.get_symbol_table(), options);
+        parser.parse(tokens)
+    })?;
+    warnings.extend(parser.get_warnings().iter().cloned());
+    Ok(Assembly {
+        words,
+        symbols: parser.get_symbol_table().clone(),
+        warnings,
+        tokens,
+    })
+}
+
+/// Assembles like [`assemble_with`] after running [`optimizer::optimize`]
+/// over the program, returning the assembly along with the number of words
+/// the optimiser saved.
+pub fn assemble_optimized<'a>(
+    code: &'a str,
+    file_name: &str,
+    mut parser: Parser,
+) -> Result<(Assembly<'a>, usize), Vec<AssembleError>> {
+    let mut errors = Vec::new();
+    let optimized = optimizer::optimize(lex(code, file_name, &mut errors));
+
+    let words = passes(&mut parser, &optimized.tokens, errors, |parser, tokens| {
+        parser.parse(tokens)
+    })?;
+    let assembly = Assembly {
+        words,
+        symbols: parser.get_symbol_table().clone(),
+        warnings: parser.get_warnings().clone(),
+        tokens: optimized.tokens,
+    };
+    Ok((assembly, optimized.saved))
+}
+
+/// Lexes `code`, adding any errors to `errors`. The lines with errors are
+/// then left out, so the parser can still check the rest of the program.
+/// So are the instructions using a name whose definition was left out, which
+/// would only be reported as undefined or out of range.
+fn lex<'a>(code: &'a str, file_name: &str, errors: &mut Vec<AssembleError>) -> Vec<Token<'a>> {
+    match Lexer::new(code, file_name) {
+        Ok(lexer) => lexer.into_tokens(),
+        Err(found) => {
+            let mut tokens = recover_tokens(code, file_name, &found);
+            let rejected: HashSet<&str> = found
+                .iter()
+                .filter_map(|error| defined_name(&error.source_line))
+                .collect();
+            tokens.retain(|token| !uses_any(token, &rejected));
+            errors.extend(found);
+            tokens
+        }
+    }
+}
+
+/// The label, constant or RAM block `line` defines, if it is a definition.
+fn defined_name(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if let Some(label) = line.strip_prefix('(') {
+        return label.split(|c: char| c == ')' || c.is_whitespace()).next();
+    }
+    let mut words = line.split_whitespace();
+    match words.next()? {
+        ".equ" | ".define" | ".var" | ".data" => words.next(),
+        _ => None,
+    }
+}
+
+/// Whether the operand or value of `token` uses one of `names`.
+fn uses_any(token: &Token, names: &HashSet<&str>) -> bool {
+    match token.get_token() {
+        Instruction::AInstruction(AValue::Symbol(name)) => names.contains(name.as_ref()),
+        Instruction::AInstruction(AValue::Expression(expr))
+        | Instruction::Directive(Directive::Constant { value: expr, .. })
+        | Instruction::Directive(Directive::Var { size: expr, .. }) => {
+            expr.symbols().iter().any(|name| names.contains(name))
+        }
+        _ => false,
+    }
+}
+
+/// Runs `Parser::first_pass` and then `pass` over `tokens`, after `errors`
+/// from lexing. Both always run; an error `pass` repeats, such as the
+/// program not fitting in ROM, is reported once.
+fn passes<T>(
+    parser: &mut Parser,
+    tokens: &[Token],
+    mut errors: Vec<AssembleError>,
+    pass: impl FnOnce(&mut Parser, &[Token]) -> Result<T, Vec<AssembleError>>,
+) -> Result<T, Vec<AssembleError>> {
+    if let Err(found) = parser.first_pass(tokens) {
+        errors.extend(found);
+    }
+    match pass(parser, tokens) {
+        Ok(value) if errors.is_empty() => Ok(value),
+        Ok(_) => Err(errors),
+        Err(found) => {
+            for error in found {
+                if !errors.contains(&error) {
+                    errors.push(error);
+                }
+            }
+            Err(errors)
+        }
+    }
+}
+
+/// Expands every macro and `.include` in `code`, returning the resulting
+/// source. Each macro call and include is kept as a comment above the
+/// lines it produced.
+pub fn expand_str(code: &str, file_name: &str) -> Result<String, Vec<AssembleError>> {
+    let lexer = Lexer::new(code, file_name)?;
+    let mut expanded = String::new();
+    for token in lexer.get_tokens() {
+        let line = match token.get_token() {
+            Instruction::Directive(Directive::Include(_))
+            | Instruction::Directive(Directive::MacroCall { .. }) => {
+                format!("// {}", token.source().trim())
+            }
+            Instruction::LInstruction(_) => token.get_token().to_string(),
+            // The prologue storing the values is already part of the output
+            Instruction::Directive(Directive::Data { name, values }) => {
+                format!("    .var {} {}", name, values.len())
+            }
+            instruction => format!("    {}", instruction),
+        };
+        expanded.push_str(&line);
+        expanded.push('\n');
+    }
+    Ok(expanded)
+}
+
+/// Reads all of `reader` and assembles it like [`assemble_str`].
+pub fn assemble_reader<R: Read>(
+    mut reader: R,
+    file_name: &str,
+) -> Result<Assembly<'static>, Vec<AssembleError>> {
+    let mut code = String::new();
+    if let Err(error) = reader.read_to_string(&mut code) {
+        return Err(vec![AssembleError::file(
+            format!("could not read input: {}", error),
+            file_name,
+        )]);
     }
+    assemble_str(&code, file_name).map(Assembly::into_owned)
 }
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use crate::config::{Allocator, Config};
+use crate::error::AssembleError;
+use crate::object::{Object, RelocationKind};
+use crate::parser::{MAX_ADDRESS, ROM_SIZE};
+use crate::symbols::{Symbol, SymbolKind};
+
+/// A program linked from one or more objects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Linked {
+    pub words: Vec<u16>,
+    /// The exported labels and the variables, at their final addresses.
+    pub symbols: HashMap<String, Symbol>,
+    pub warnings: Vec<AssembleError>,
+}
+
+/// Links `modules`, each an object with the name of the file it came from,
+/// for the machine described by `config`. Modules are laid out in ROM in the
+/// order given, so the first one starts at address 0. Variables are allocated
+/// upward from the variable base across all modules, and modules using a
+/// variable of the same name share its address. Labels of other modules are
+/// only reached through `.extern`, so a variable named like an exported label
+/// is an error rather than silently becoming a reference to it.
+pub fn link(modules: &[(String, Object)], config: &Config) -> Result<Linked, Vec<AssembleError>> {
+    let mut errors = Vec::new();
+    let mut symbols = HashMap::new();
+
+    let mut bases = Vec::new();
+    let mut size = 0;
+    for (_, object) in modules {
+        bases.push(size);
+        size += object.words.len();
+    }
+    if size > config.rom_size {
+        let file = modules.last().map_or("", |(file, _)| file.as_str());
+        return Err(vec![AssembleError::file(
+            format!(
+                "linked program needs {} words of ROM but only {} are available",
+                size, config.rom_size
+            ),
+            file,
+        )]);
+    }
+
+    let mut exporters: HashMap<&str, &str> = HashMap::new();
+    for ((file, object), base) in modules.iter().zip(&bases) {
+        for (name, offset) in &object.exports {
+            if let Some(first) = exporters.insert(name, file) {
+                errors.push(
+                    AssembleError::file(format!("duplicate symbol '{}'", name), file)
+                        .with_note(format!("also exported by {}", first)),
+                );
+                continue;
+            }
+            // `Object::read` checks offsets, but objects built in memory may be off
+            let address = base + *offset as usize;
+            let message = if *offset as usize > object.words.len() {
+                format!(
+                    "exported label '{}' at offset {} is past the end of the module",
+                    name, offset
+                )
+            } else if address > ROM_SIZE {
+                format!(
+                    "exported label '{}' is at {}, past the end of the address space",
+                    name, address
+                )
+            } else {
+                symbols.insert(
+                    name.clone(),
+                    Symbol::new(address as u16, SymbolKind::Label),
+                );
+                continue;
+            };
+            errors.push(AssembleError::file(message, file));
+        }
+    }
+
+    let mut allocator = Allocator::new(config);
+    let mut warnings = Vec::new();
+    for (file, object) in modules {
+        for name in &object.variables {
+            if let Some(exporter) = exporters.get(name.as_str()) {
+                errors.push(
+                    AssembleError::file(
+                        format!(
+                            "variable '{}' has the name of a label exported by {}",
+                            name, exporter
+                        ),
+                        file,
+                    )
+                    .with_note(format!("declare it with '.extern {}' to use the label", name)),
+                );
+                continue;
+            }
+            if symbols.contains_key(name) {
+                continue;
+            }
+            let allocation = match allocator.allocate(config, 1) {
+                Some(allocation) => allocation,
+                None => {
+                    errors.push(AssembleError::file(
+                        format!("no RAM left to allocate variable '{}'", name),
+                        file,
+                    ));
+                    continue;
+                }
+            };
+            if allocation.past_limit {
+                let message = format!(
+                    "variable '{}' is allocated at {}, past the RAM limit of {}",
+                    name, allocation.address, config.ram_limit
+                );
+                warnings.push(AssembleError::file(message, file).into_warning());
+            }
+            symbols.insert(
+                name.clone(),
+                Symbol::new(allocation.address, SymbolKind::Variable),
+            );
+        }
+    }
+
+    for (file, object) in modules {
+        for name in &object.imports {
+            if !symbols.contains_key(name) {
+                errors.push(AssembleError::file(
+                    format!("unresolved symbol '{}': no module exports it", name),
+                    file,
+                ));
+            }
+        }
+    }
+
+    let mut words = Vec::with_capacity(size);
+    for ((file, object), base) in modules.iter().zip(&bases) {
+        let mut module = object.words.clone();
+        for relocation in &object.relocations {
+            let word = &mut module[relocation.offset];
+            let address = match &relocation.kind {
+                RelocationKind::Label => *word as usize + base,
+                RelocationKind::Import(name) | RelocationKind::Variable(name) => {
+                    match symbols.get(name) {
+                        Some(symbol) => symbol.address as usize,
+                        // Reported above, or not declared with `.extern`
+                        None if object.imports.contains(name) => continue,
+                        None => {
+                            errors.push(AssembleError::file(
+                                format!("unresolved symbol '{}'", name),
+                                file,
+                            ));
+                            continue;
+                        }
+                    }
+                }
+            };
+            if address > MAX_ADDRESS as usize {
+                errors.push(AssembleError::file(
+                    format!(
+                        "word {} relocated to {}, which does not fit in 15 bits",
+                        relocation.offset, address
+                    ),
+                    file,
+                ));
+                continue;
+            }
+            *word = address as u16;
+        }
+        words.extend(module);
+    }
+
+    if errors.is_empty() {
+        Ok(Linked {
+            words,
+            symbols,
+            warnings,
+        })
+    } else {
+        Err(errors)
+    }
+}
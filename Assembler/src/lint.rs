@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::error::AssembleError;
+use crate::lexer::{AValue, Comp, Dest, Directive, Instruction, Jump, Token};
+use crate::symbols::{Symbol, SymbolKind};
+
+/// The checks [`lint`] runs beyond the default ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Report C-instructions such as `AM=M-1` that write `A` together with
+    /// another destination while reading `M`, where `M` is the address
+    /// before the write. VM translators pop the stack this way on purpose,
+    /// so it is off by default.
+    pub strict: bool,
+}
+
+/// Checks `tokens` for code that assembles but is probably wrong, returning
+/// one warning per finding in source order. `symbols` is the symbol table
+/// after `Parser::first_pass`, so it holds labels and constants but no
+/// variables yet.
+pub fn lint(
+    tokens: &[Token],
+    symbols: &HashMap<String, Symbol>,
+    options: Options,
+) -> Vec<AssembleError> {
+    let mut findings = Vec::new();
+    unused_labels(tokens, &mut findings);
+    implicit_variables(tokens, symbols, &mut findings);
+    instructions(tokens, options, &mut findings);
+
+    findings.sort_by_key(|(index, _)| *index);
+    findings
+        .into_iter()
+        .map(|(_, warning)| warning.into_warning())
+        .collect()
+}
+
+/// Warnings paired with the index of the token they are about.
+type Findings = Vec<(usize, AssembleError)>;
+
+fn unused_labels(tokens: &[Token], findings: &mut Findings) {
+    let mut referenced = HashSet::new();
+    for token in tokens {
+        match token.get_token() {
+            Instruction::AInstruction(AValue::Symbol(symbol)) => {
+                referenced.insert(symbol.as_ref());
+            }
+            Instruction::AInstruction(AValue::Expression(expr))
+            | Instruction::Directive(Directive::Constant { value: expr, .. }) => {
+                referenced.extend(expr.symbols());
+            }
+            // Exported labels are used by other modules
+            Instruction::Directive(Directive::Global(label)) => {
+                referenced.insert(label.as_str());
+            }
+            _ => {}
+        }
+    }
+
+    for (index, token) in tokens.iter().enumerate() {
+        if let Instruction::LInstruction(label) = token.get_token() {
+            if !referenced.contains(label.as_ref()) {
+                let message = format!("label '{}' is never used", label);
+                findings.push((index, token.error(message, 0, token.width())));
+            }
+        }
+    }
+}
+
+/// Flags symbols that silently became variables although they look like a
+/// misspelt label or predefined symbol.
+fn implicit_variables(
+    tokens: &[Token],
+    symbols: &HashMap<String, Symbol>,
+    findings: &mut Findings,
+) {
+    let mut variables = HashSet::new();
+    // Imported labels are resolved by the linker, not allocated
+    for token in tokens {
+        if let Instruction::Directive(Directive::Extern(name)) = token.get_token() {
+            variables.insert(name.as_str());
+        }
+    }
+    for (index, token) in tokens.iter().enumerate() {
+        let variable = match token.get_token() {
+            Instruction::AInstruction(AValue::Symbol(symbol)) => symbol.as_ref(),
+            _ => continue,
+        };
+        if symbols.contains_key(variable) || !variables.insert(variable) {
+            continue;
+        }
+
+        if let Some(similar) = similar_symbol(variable, symbols) {
+            let kind = match symbols[similar].kind {
+                SymbolKind::Predefined => "predefined symbol",
+                kind => kind.name(),
+            };
+            let message = format!(
+                "'{}' is allocated as a variable, but looks like a misspelling of {} '{}'",
+                variable, kind, similar
+            );
+            findings.push((index, token.error(message, 1, token.width() - 1)));
+        }
+    }
+}
+
+/// The closest label, constant or predefined symbol `name` could be a typo
+/// of: one that differs by at most a single edit, ignoring case.
+fn similar_symbol<'a>(name: &str, symbols: &'a HashMap<String, Symbol>) -> Option<&'a str> {
+    if name.chars().count() < 3 {
+        return None;
+    }
+    let mut candidates: Vec<&str> = symbols
+        .keys()
+        .map(String::as_str)
+        .filter(|symbol| edit_distance(&symbol.to_lowercase(), &name.to_lowercase()) <= 1)
+        .collect();
+    // Prefer labels over predefined symbols, then the alphabetically first
+    candidates.sort_by_key(|symbol| (symbols[*symbol].kind == SymbolKind::Predefined, *symbol));
+    candidates.first().copied()
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Checks each C-instruction against the instruction before it.
+fn instructions(tokens: &[Token], options: Options, findings: &mut Findings) {
+    let mut previous: Option<&Instruction> = None;
+    let mut unreachable = false;
+    for (index, token) in tokens.iter().enumerate() {
+        let instruction = token.get_token();
+        match instruction {
+            Instruction::Directive(_) => continue,
+            Instruction::LInstruction(_) => {
+                // Anything after a label can be jumped to
+                unreachable = false;
+                continue;
+            }
+            _ => {}
+        }
+
+        if unreachable {
+            let message = String::from("unreachable instruction after an unconditional jump");
+            findings.push((index, token.error(message, 0, token.width())));
+            unreachable = false;
+        }
+
+        if let Instruction::CInstruction { dest, comp, jump } = instruction {
+            // `A=M` is the usual pointer dereference, so only combined
+            // destinations such as `AM=M-1` are reported
+            if options.strict && writes_a(*dest) && *dest != Dest::A && reads_m(*comp) {
+                let message = format!(
+                    "'{}' writes A and reads M; M refers to the address in A before the write",
+                    instruction
+                );
+                findings.push((index, token.error(message, 0, token.width())));
+            }
+            if *jump != Jump::Null && !previous.is_some_and(loads_a) {
+                let message = format!(
+                    "'{}' jumps to the address in A, but the previous instruction did not load A",
+                    instruction
+                );
+                findings.push((index, token.error(message, 0, token.width())));
+            }
+            if *jump == Jump::JMP {
+                unreachable = true;
+            }
+        }
+        previous = Some(instruction);
+    }
+}
+
+fn loads_a(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::AInstruction(_) => true,
+        Instruction::CInstruction { dest, .. } => writes_a(*dest),
+        _ => false,
+    }
+}
+
+fn writes_a(dest: Dest) -> bool {
+    matches!(dest, Dest::A | Dest::AM | Dest::AD | Dest::AMD)
+}
+
+fn reads_m(comp: Comp) -> bool {
+    comp.mnemonic().contains('M')
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Write};
+
+use crate::lexer::Instruction;
+use crate::Assembly;
+
+/// Writes a human-readable listing of `assembly`: for every instruction its
+/// ROM address, the encoded word in binary and hex, and the source line it
+/// came from. Labels are listed at the address `Parser::first_pass` gave them,
+/// and directives without an address. Whenever the listing moves into another
+/// file, such as one pulled in with `.include`, a line naming it is written.
+///
+/// ```text
+///   ROM  Binary            Hex    Line  Source
+/// 00002                             3  (LOOP)
+/// 00002  0000000000010000  0010     4  @i
+/// ```
+pub fn write_listing(assembly: &Assembly, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(writer, "  ROM  Binary            Hex    Line  Source")?;
+    let mut words = assembly.words.iter().enumerate();
+    let mut file = None;
+    for token in &assembly.tokens {
+        if file != Some(token.file()) {
+            file = Some(token.file());
+            writeln!(writer, "{:>36}  // {}", "", token.file())?;
+        }
+        let source = token.source().trim_end();
+        match token.get_token() {
+            Instruction::LInstruction(label) => {
+                // Left blank for a label missing from the symbol table
+                let address = assembly
+                    .symbols
+                    .get(label.as_ref())
+                    .map_or(String::new(), |symbol| format!("{:05}", symbol.address));
+                writeln!(
+                    writer,
+                    "{:5}  {:16}  {:4}  {:>5}  {}",
+                    address,
+                    "",
+                    "",
+                    token.line(),
+                    source
+                )?
+            }
+            Instruction::Directive(_) => writeln!(
+                writer,
+                "{:5}  {:16}  {:4}  {:>5}  {}",
+                "",
+                "",
+                "",
+                token.line(),
+                source
+            )?,
+            _ => {
+                let (address, word) = words
+                    .next()
+                    .expect("every instruction is encoded to exactly one word");
+                writeln!(
+                    writer,
+                    "{:05}  {:016b}  {:04X}  {:>5}  {}",
+                    address,
+                    word,
+                    word,
+                    token.line(),
+                    source
+                )?
+            }
+        }
+    }
+    Ok(())
+}
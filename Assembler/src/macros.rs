@@ -0,0 +1,84 @@
+use std::rc::Rc;
+
+use crate::lexer::is_symbol_char;
+
+/// A macro defined with `.macro NAME param, ...` and closed by `.endm`.
+#[derive(Debug)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub file: Rc<str>,
+    /// The 1-based line number of the `.macro` line.
+    pub line: usize,
+    /// The body lines, each with its 1-based line number.
+    pub body: Vec<(usize, String)>,
+    /// Labels declared in the body, which get a unique name in every expansion.
+    pub labels: Vec<String>,
+}
+
+impl Macro {
+    pub fn new(name: String, params: Vec<String>, file: Rc<str>, line: usize) -> Macro {
+        Macro {
+            name,
+            params,
+            file,
+            line,
+            body: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn push_line(&mut self, line_number: usize, line: &str) {
+        let trimmed = line.trim();
+        if let Some(label) = trimmed.strip_prefix('(') {
+            let label = symbol_word(label.trim_start());
+            if !label.is_empty() && !self.labels.iter().any(|known| known == label) {
+                self.labels.push(String::from(label));
+            }
+        }
+        self.body.push((line_number, String::from(line)));
+    }
+
+    /// Expands one body line for expansion number `id`: every `\param` is
+    /// replaced by its argument, and in A-instructions and label declarations
+    /// the body's own labels are renamed to their unique per-expansion name.
+    pub fn expand_line(&self, line: &str, args: &[String], id: usize) -> String {
+        let rename_labels = matches!(line.trim_start().chars().next(), Some('@') | Some('('));
+        let mut expanded = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            if c == '\\' {
+                let word = symbol_word(&rest[1..]);
+                if let Some(index) = self.params.iter().position(|param| param == word) {
+                    expanded.push_str(&args[index]);
+                    rest = &rest[1 + word.len()..];
+                    continue;
+                }
+            } else if is_symbol_char(c) {
+                let word = symbol_word(rest);
+                if rename_labels && self.labels.iter().any(|label| label == word) {
+                    expanded.push_str(&self.local_label(word, id));
+                } else {
+                    expanded.push_str(word);
+                }
+                rest = &rest[word.len()..];
+                continue;
+            }
+            expanded.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        expanded
+    }
+
+    /// The name `label` gets in expansion number `id`, such as `COPY$3$LOOP`.
+    pub fn local_label(&self, label: &str, id: usize) -> String {
+        format!("{}${}${}", self.name, id, label)
+    }
+}
+
+fn symbol_word(text: &str) -> &str {
+    let len = text
+        .find(|c: char| !is_symbol_char(c))
+        .unwrap_or(text.len());
+    &text[..len]
+}
@@ -0,0 +1,211 @@
+use std::io::{self, Write};
+
+use crate::error::AssembleError;
+use crate::lexer::is_symbol;
+
+/// The first line of every object file.
+const MAGIC: &str = "HACKOBJ 1";
+
+/// A separately assembled module, ready to be placed anywhere in ROM by the
+/// linker. Label addresses in `words` are relative to the start of the module.
+///
+/// Objects are stored as text, one entry per line:
+///
+/// ```text
+/// HACKOBJ 1
+/// export Math.multiply 0
+/// import Sys.halt
+/// variable counter
+/// code 4
+/// 0002 label
+/// EA87
+/// 0000 import Sys.halt
+/// 0000 variable counter
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Object {
+    pub words: Vec<u16>,
+    /// Words the linker has to patch, in ROM order.
+    pub relocations: Vec<Relocation>,
+    /// Labels other modules may refer to, with their offset in the module.
+    pub exports: Vec<(String, u16)>,
+    /// Labels declared with `.extern`, which another module must export.
+    pub imports: Vec<String>,
+    /// Symbols the module uses as variables, in order of first use.
+    pub variables: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// The index of the word to patch within the module.
+    pub offset: usize,
+    pub kind: RelocationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// A reference to a label in the same module: the module's ROM address
+    /// is added to the word.
+    Label,
+    /// The word is replaced by the address of a label exported by another module.
+    Import(String),
+    /// The word is replaced by the RAM address the linker allocates for the variable.
+    Variable(String),
+}
+
+impl Object {
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writeln!(writer, "{}", MAGIC)?;
+        for (name, offset) in &self.exports {
+            writeln!(writer, "export {} {}", name, offset)?;
+        }
+        for name in &self.imports {
+            writeln!(writer, "import {}", name)?;
+        }
+        for name in &self.variables {
+            writeln!(writer, "variable {}", name)?;
+        }
+
+        writeln!(writer, "code {}", self.words.len())?;
+        let mut relocations = self.relocations.iter().peekable();
+        for (offset, word) in self.words.iter().enumerate() {
+            write!(writer, "{:04X}", word)?;
+            if let Some(relocation) = relocations.next_if(|r| r.offset == offset) {
+                match &relocation.kind {
+                    RelocationKind::Label => write!(writer, " label")?,
+                    RelocationKind::Import(name) => write!(writer, " import {}", name)?,
+                    RelocationKind::Variable(name) => write!(writer, " variable {}", name)?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an object file written by [`Object::write`]. `file` is only
+    /// used to label errors.
+    pub fn read(code: &str, file: &str) -> Result<Object, Vec<AssembleError>> {
+        let mut object = Object::default();
+        let mut errors = Vec::new();
+        let mut lines = code.lines().enumerate();
+        let error = |index: usize, line: &str, message: String| {
+            AssembleError::new(message, file, index + 1, 1, line.chars().count(), line)
+        };
+
+        match lines.next() {
+            Some((_, MAGIC)) => {}
+            _ => {
+                let message = format!("not a Hack object file (expected '{}' first)", MAGIC);
+                return Err(vec![AssembleError::file(message, file)]);
+            }
+        }
+
+        let mut size = None;
+        // Where each export was read, to check its offset once the code is
+        let mut export_lines = Vec::new();
+        for (index, line) in lines.by_ref() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["export", name, offset] if is_symbol(name) => match offset.parse() {
+                    Ok(offset) => {
+                        object.exports.push((String::from(*name), offset));
+                        export_lines.push((index, line));
+                    }
+                    Err(_) => {
+                        errors.push(error(index, line, format!("invalid offset '{}'", offset)))
+                    }
+                },
+                ["import", name] if is_symbol(name) => object.imports.push(String::from(*name)),
+                ["variable", name] if is_symbol(name) => object.variables.push(String::from(*name)),
+                ["code", count] => match count.parse::<usize>() {
+                    Ok(count) => {
+                        size = Some(count);
+                        break;
+                    }
+                    Err(_) => errors.push(error(
+                        index,
+                        line,
+                        format!("invalid word count '{}'", count),
+                    )),
+                },
+                [] => {}
+                _ => errors.push(error(
+                    index,
+                    line,
+                    format!("invalid object entry '{}'", line.trim()),
+                )),
+            }
+        }
+
+        for (index, line) in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (word, kind) = match fields.as_slice() {
+                [word] => (word, None),
+                [word, "label"] => (word, Some(RelocationKind::Label)),
+                [word, "import", name] if is_symbol(name) => {
+                    (word, Some(RelocationKind::Import(String::from(*name))))
+                }
+                [word, "variable", name] if is_symbol(name) => {
+                    (word, Some(RelocationKind::Variable(String::from(*name))))
+                }
+                [] => continue,
+                _ => {
+                    errors.push(error(
+                        index,
+                        line,
+                        format!("invalid code entry '{}'", line.trim()),
+                    ));
+                    continue;
+                }
+            };
+            match u16::from_str_radix(word, 16) {
+                Ok(value) if word.len() == 4 => {
+                    if let Some(kind) = kind {
+                        object.relocations.push(Relocation {
+                            offset: object.words.len(),
+                            kind,
+                        });
+                    }
+                    object.words.push(value);
+                }
+                _ => errors.push(error(index, line, format!("invalid word '{}'", word))),
+            }
+        }
+
+        match size {
+            None => errors.push(AssembleError::file(
+                String::from("missing 'code' section"),
+                file,
+            )),
+            Some(size) if size != object.words.len() && errors.is_empty() => {
+                errors.push(AssembleError::file(
+                    format!(
+                        "expected {} words of code but found {}",
+                        size,
+                        object.words.len()
+                    ),
+                    file,
+                ))
+            }
+            _ => {}
+        }
+        // A label may follow the last word, but not lie beyond it
+        for ((name, offset), (index, line)) in object.exports.iter().zip(export_lines) {
+            if *offset as usize > object.words.len() {
+                let message = format!(
+                    "export '{}' at offset {} is past the end of the {} words of code",
+                    name,
+                    offset,
+                    object.words.len()
+                );
+                errors.push(error(index, line, message));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(object)
+        } else {
+            Err(errors)
+        }
+    }
+}
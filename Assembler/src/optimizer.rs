@@ -0,0 +1,273 @@
+use crate::lexer::{AValue, Comp, Dest, Instruction, Jump, Token};
+
+/// The result of [`optimize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimization<'a> {
+    /// The remaining tokens, in program order.
+    pub tokens: Vec<Token<'a>>,
+    /// How many words of ROM the removed instructions took.
+    pub saved: usize,
+}
+
+/// Runs a peephole optimiser over `tokens` until nothing more can be removed.
+/// It deletes
+///
+/// * `M=M+1` directly followed by `M=M-1` on the same address, or the reverse,
+///   as the VM translator emits for a push followed by a pop;
+/// * A-instructions that load the value A already holds, such as the second
+///   `@SP` in `@SP / M=M-1 / @SP / A=M`, or whose value is overwritten
+///   before it is used;
+/// * instructions that only write D when D is overwritten before it is read;
+/// * jumps to the instruction right after them.
+///
+/// Only instructions are removed, never labels, so labels are re-resolved by
+/// `Parser::first_pass` once code has moved. The optimiser assumes code is
+/// only reached through labels, not through addresses computed from them
+/// such as `@LOOP+2`.
+pub fn optimize(mut tokens: Vec<Token<'_>>) -> Optimization<'_> {
+    let words = |tokens: &[Token]| tokens.iter().filter(|token| token.emits_word()).count();
+    let before = words(&tokens);
+    loop {
+        let mut removed = vec![false; tokens.len()];
+        redundant_loads(&tokens, &mut removed);
+        cancelled_adjustments(&tokens, &mut removed);
+        dead_instructions(&tokens, &mut removed);
+        if !removed.contains(&true) {
+            break;
+        }
+        let mut removed = removed.into_iter();
+        tokens.retain(|_| !removed.next().unwrap_or(false));
+    }
+    let saved = before - words(&tokens);
+    Optimization { tokens, saved }
+}
+
+/// What A is known to hold at a point in the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Known<'a> {
+    /// A was loaded with this value.
+    Address(&'a AValue<'a>),
+    /// A was loaded from the word at this address, as by `@SP / A=M`.
+    Pointer(&'a AValue<'a>),
+}
+
+/// Removes `@X` when A already holds `X`, and `@X / A=M` when A already
+/// holds the word at `X`. What A holds is forgotten at every label, since
+/// other paths may jump there.
+fn redundant_loads(tokens: &[Token], removed: &mut [bool]) {
+    let mut known = None;
+    let mut index = 0;
+    while index < tokens.len() {
+        if removed[index] {
+            index += 1;
+            continue;
+        }
+        match tokens[index].get_token() {
+            Instruction::LInstruction(_) => known = None,
+            Instruction::Directive(_) => {}
+            Instruction::AInstruction(value) => {
+                if known == Some(Known::Address(value)) {
+                    removed[index] = true;
+                } else if known == Some(Known::Pointer(value)) {
+                    match next_instruction(tokens, removed, index) {
+                        Some(next) if is_dereference(tokens[next].get_token()) => {
+                            removed[index] = true;
+                            removed[next] = true;
+                            index = next;
+                        }
+                        _ => known = Some(Known::Address(value)),
+                    }
+                } else {
+                    known = Some(Known::Address(value));
+                }
+            }
+            instruction @ Instruction::CInstruction { dest, .. } => {
+                known = match known {
+                    Some(Known::Address(value)) if is_dereference(instruction) => {
+                        Some(Known::Pointer(value))
+                    }
+                    _ if writes_a(*dest) => None,
+                    // The write may have changed the word A was loaded from
+                    Some(Known::Pointer(_)) if writes_m(*dest) => None,
+                    known => known,
+                };
+            }
+        }
+        index += 1;
+    }
+}
+
+/// Removes `M=M+1` directly followed by `M=M-1`, or the reverse. Neither
+/// changes A, so both update the same word and leave it as it was.
+fn cancelled_adjustments(tokens: &[Token], removed: &mut [bool]) {
+    let adjustment = |token: &Token| match token.get_token() {
+        Instruction::CInstruction {
+            dest: Dest::M,
+            comp: comp @ (Comp::MPlusOne | Comp::MMinusOne),
+            jump: Jump::Null,
+        } => Some(*comp),
+        _ => None,
+    };
+    for index in 0..tokens.len() {
+        if removed[index] {
+            continue;
+        }
+        let first = match adjustment(&tokens[index]) {
+            Some(comp) => comp,
+            None => continue,
+        };
+        if let Some(next) = next_instruction(tokens, removed, index) {
+            if adjustment(&tokens[next]).is_some_and(|second| second != first) {
+                removed[index] = true;
+                removed[next] = true;
+            }
+        }
+    }
+}
+
+/// Removes instructions whose only effect is lost: A-instructions and
+/// `D=...` instructions whose result is overwritten before it is read, and
+/// jumps to the next instruction. Works backward, so every decision sees the
+/// removals after it.
+fn dead_instructions(tokens: &[Token], removed: &mut [bool]) {
+    for index in (0..tokens.len()).rev() {
+        if removed[index] {
+            continue;
+        }
+        match tokens[index].get_token() {
+            Instruction::AInstruction(value) => {
+                if let Some(jump) = jump_to_next(tokens, removed, index, value) {
+                    if !a_is_live(tokens, removed, jump + 1) {
+                        removed[index] = true;
+                        removed[jump] = true;
+                    }
+                } else if !a_is_live(tokens, removed, index + 1) {
+                    removed[index] = true;
+                }
+            }
+            Instruction::CInstruction {
+                dest: Dest::D,
+                jump: Jump::Null,
+                ..
+            } if !d_is_live(tokens, removed, index + 1) => removed[index] = true,
+            _ => {}
+        }
+    }
+}
+
+/// If the A-instruction at `index` is followed by a jump that only lands on
+/// the label it loads, returns the index of the jump.
+fn jump_to_next(tokens: &[Token], removed: &[bool], index: usize, value: &AValue) -> Option<usize> {
+    let label = match value {
+        AValue::Symbol(label) => label,
+        _ => return None,
+    };
+    let jump = next_instruction(tokens, removed, index)?;
+    match tokens[jump].get_token() {
+        Instruction::CInstruction {
+            dest: Dest::Null,
+            jump,
+            ..
+        } if *jump != Jump::Null => {}
+        _ => return None,
+    }
+    let lands = tokens[jump + 1..]
+        .iter()
+        .zip(&removed[jump + 1..])
+        .filter(|(_, removed)| !**removed)
+        .map(|(token, _)| token)
+        .take_while(|token| !token.emits_word())
+        .any(|token| matches!(token.get_token(), Instruction::LInstruction(name) if name == label));
+    if lands {
+        Some(jump)
+    } else {
+        None
+    }
+}
+
+/// Whether the value in A at `start` may be used before A is loaded again.
+/// Execution is followed through labels; a jump or the end of the program
+/// counts as a use.
+fn a_is_live(tokens: &[Token], removed: &[bool], start: usize) -> bool {
+    for (token, _) in tokens[start..]
+        .iter()
+        .zip(&removed[start..])
+        .filter(|(_, removed)| !**removed)
+    {
+        match token.get_token() {
+            Instruction::AInstruction(_) => return false,
+            Instruction::CInstruction { dest, comp, jump } => {
+                if reads(*comp, 'A') || reads(*comp, 'M') || writes_m(*dest) {
+                    return true;
+                }
+                if *jump != Jump::Null {
+                    return true;
+                }
+                if writes_a(*dest) {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Whether the value in D at `start` may be used before D is written again.
+/// A jump may lead to code that reads D, so it counts as a use, as does the
+/// end of the program.
+fn d_is_live(tokens: &[Token], removed: &[bool], start: usize) -> bool {
+    for (token, _) in tokens[start..]
+        .iter()
+        .zip(&removed[start..])
+        .filter(|(_, removed)| !**removed)
+    {
+        if let Instruction::CInstruction { dest, comp, jump } = token.get_token() {
+            if reads(*comp, 'D') || *jump != Jump::Null {
+                return true;
+            }
+            if matches!(dest, Dest::D | Dest::MD | Dest::AD | Dest::AMD) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// The index of the first instruction after `index` that is not removed,
+/// unless a label comes first.
+fn next_instruction(tokens: &[Token], removed: &[bool], index: usize) -> Option<usize> {
+    for next in index + 1..tokens.len() {
+        if removed[next] {
+            continue;
+        }
+        match tokens[next].get_token() {
+            Instruction::LInstruction(_) => return None,
+            Instruction::Directive(_) => continue,
+            _ => return Some(next),
+        }
+    }
+    None
+}
+
+/// Whether `instruction` is `A=M`.
+fn is_dereference(instruction: &Instruction) -> bool {
+    *instruction
+        == Instruction::CInstruction {
+            dest: Dest::A,
+            comp: Comp::M,
+            jump: Jump::Null,
+        }
+}
+
+fn reads(comp: Comp, register: char) -> bool {
+    comp.mnemonic().contains(register)
+}
+
+fn writes_a(dest: Dest) -> bool {
+    matches!(dest, Dest::A | Dest::AM | Dest::AD | Dest::AMD)
+}
+
+fn writes_m(dest: Dest) -> bool {
+    matches!(dest, Dest::M | Dest::MD | Dest::AM | Dest::AMD)
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Write};
+
+/// The ways machine code can be written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One word per line as 16 binary digits, as read by the CPU emulator.
+    Hack,
+    /// One word per line as 4 hexadecimal digits.
+    Hex,
+    /// Packed 16-bit words, least significant byte first.
+    BinaryLe,
+    /// Packed 16-bit words, most significant byte first.
+    BinaryBe,
+    /// Intel HEX records, two bytes per word with the high byte first.
+    IntelHex,
+    /// A Verilog memory file for `$readmemb`.
+    ReadMemB,
+    /// A Verilog memory file for `$readmemh`.
+    ReadMemH,
+    /// A Logisim "v2.0 raw" ROM image.
+    Logisim,
+}
+
+/// Data bytes per Intel HEX record.
+const INTEL_HEX_RECORD_LEN: usize = 16;
+/// Values per line in a Logisim image.
+const LOGISIM_LINE_LEN: usize = 8;
+/// The shortest run of equal values worth writing as `count*value` in a Logisim image.
+const LOGISIM_MIN_RUN: usize = 4;
+
+impl Format {
+    pub const NAMES: [&'static str; 8] = [
+        "hack", "hex", "bin-le", "bin-be", "ihex", "readmemb", "readmemh", "logisim",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        let format = match name {
+            "hack" => Format::Hack,
+            "hex" => Format::Hex,
+            "bin-le" => Format::BinaryLe,
+            "bin-be" => Format::BinaryBe,
+            "ihex" => Format::IntelHex,
+            "readmemb" => Format::ReadMemB,
+            "readmemh" => Format::ReadMemH,
+            "logisim" => Format::Logisim,
+            _ => return None,
+        };
+        Some(format)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Hack => "hack",
+            Format::Hex => "hex",
+            Format::BinaryLe | Format::BinaryBe => "bin",
+            Format::IntelHex => "ihex",
+            Format::ReadMemB | Format::ReadMemH => "mem",
+            Format::Logisim => "rom",
+        }
+    }
+
+    pub fn write(&self, words: &[u16], writer: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Format::Hack => {
+                for &word in words {
+                    writer.write_all(&binary_line(word))?;
+                }
+            }
+            Format::Hex => {
+                for &word in words {
+                    writer.write_all(&hex_line(word))?;
+                }
+            }
+            Format::BinaryLe => {
+                for &word in words {
+                    writer.write_all(&word.to_le_bytes())?;
+                }
+            }
+            Format::BinaryBe => {
+                for &word in words {
+                    writer.write_all(&word.to_be_bytes())?;
+                }
+            }
+            Format::IntelHex => write_intel_hex(words, writer)?,
+            Format::ReadMemB => {
+                writeln!(writer, "// Hack ROM, {} words", words.len())?;
+                for &word in words {
+                    writer.write_all(&binary_line(word))?;
+                }
+            }
+            Format::ReadMemH => {
+                writeln!(writer, "// Hack ROM, {} words", words.len())?;
+                for &word in words {
+                    writer.write_all(&hex_line(word))?;
+                }
+            }
+            Format::Logisim => write_logisim(words, writer)?,
+        }
+        Ok(())
+    }
+}
+
+/// `word` as 16 binary digits and a newline, without going through `fmt`.
+fn binary_line(word: u16) -> [u8; 17] {
+    let mut line = [b'0'; 17];
+    for (bit, digit) in line[..16].iter_mut().enumerate() {
+        if word >> (15 - bit) & 1 == 1 {
+            *digit = b'1';
+        }
+    }
+    line[16] = b'\n';
+    line
+}
+
+/// `word` as 4 lower-case hex digits and a newline.
+fn hex_line(word: u16) -> [u8; 5] {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut line = [b'\n'; 5];
+    for (nibble, digit) in line[..4].iter_mut().enumerate() {
+        *digit = DIGITS[(word >> (12 - 4 * nibble) & 0xf) as usize];
+    }
+    line
+}
+
+/// Writes data records followed by an end-of-file record. A full 32K ROM is
+/// exactly 64K bytes, so no extended address records are ever needed.
+fn write_intel_hex(words: &[u16], writer: &mut dyn Write) -> io::Result<()> {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    for (index, data) in bytes.chunks(INTEL_HEX_RECORD_LEN).enumerate() {
+        let address = (index * INTEL_HEX_RECORD_LEN) as u16;
+        let mut record = vec![data.len() as u8];
+        record.extend_from_slice(&address.to_be_bytes());
+        record.push(0x00);
+        record.extend_from_slice(data);
+        write_intel_hex_record(&record, writer)?;
+    }
+    write_intel_hex_record(&[0x00, 0x00, 0x00, 0x01], writer)
+}
+
+fn write_intel_hex_record(record: &[u8], writer: &mut dyn Write) -> io::Result<()> {
+    let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    write!(writer, ":")?;
+    for byte in record {
+        write!(writer, "{:02X}", byte)?;
+    }
+    writeln!(writer, "{:02X}", sum.wrapping_neg())
+}
+
+/// Writes the image header followed by the words in hex, collapsing long
+/// runs of the same word into Logisim's `count*value` notation.
+fn write_logisim(words: &[u16], writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(writer, "v2.0 raw")?;
+    let mut entries = Vec::new();
+    let mut index = 0;
+    while index < words.len() {
+        let word = words[index];
+        let run = words[index..].iter().take_while(|next| **next == word).count();
+        if run >= LOGISIM_MIN_RUN {
+            entries.push(format!("{}*{:x}", run, word));
+        } else {
+            entries.extend((0..run).map(|_| format!("{:x}", word)));
+        }
+        index += run;
+    }
+    for line in entries.chunks(LOGISIM_LINE_LEN) {
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
@@ -1,202 +1,685 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::lexer::{Instruction, Token};
+use crate::config::{Allocator, Config};
+use crate::error::AssembleError;
+use crate::expr::Expr;
+use crate::isa::{extension_of, InstructionSet};
+use crate::lexer::{AValue, Comp, Dest, Directive, Instruction, Jump, Token};
+use crate::object::{Object, Relocation, RelocationKind};
+use crate::symbols::{Symbol, SymbolKind};
 
-#[derive(Debug)]
-pub struct Parser<'a> {
-    comp_bits: HashMap<String, &'a str>,
-    jump_bits: HashMap<String, &'a str>,
-    dest_bits: HashMap<String, &'a str>,
-    symbol_table: HashMap<String, i32>,
+/// The highest address an A-instruction can load.
+pub const MAX_ADDRESS: u16 = 0x7fff;
+/// The number of words in the standard Hack ROM, which is also the most an
+/// A-instruction can address.
+pub const ROM_SIZE: usize = 32768;
+/// The last RAM address below the screen memory map.
+pub const RAM_LIMIT: u16 = 16383;
+
+/// Bits 15..6 of a C-instruction for a standard comp, including the `111`
+/// prefix, or `None` for an extended comp.
+pub fn encode_comp(comp: Comp) -> Option<u16> {
+    let bits = match comp {
+        Comp::Zero => 0b0101010,
+        Comp::One => 0b0111111,
+        Comp::MinusOne => 0b0111010,
+        Comp::D => 0b0001100,
+        Comp::A => 0b0110000,
+        Comp::NotD => 0b0001101,
+        Comp::NotA => 0b0110001,
+        Comp::NegD => 0b0001111,
+        Comp::NegA => 0b0110011,
+        Comp::DPlusOne => 0b0011111,
+        Comp::APlusOne => 0b0110111,
+        Comp::DMinusOne => 0b0001110,
+        Comp::AMinusOne => 0b0110010,
+        Comp::DPlusA => 0b0000010,
+        Comp::DMinusA => 0b0010011,
+        Comp::AMinusD => 0b0000111,
+        Comp::DAndA => 0b0000000,
+        Comp::DOrA => 0b0010101,
+        Comp::M => 0b1110000,
+        Comp::NotM => 0b1110001,
+        Comp::NegM => 0b1110011,
+        Comp::MPlusOne => 0b1110111,
+        Comp::MMinusOne => 0b1110010,
+        Comp::DPlusM => 0b1000010,
+        Comp::DMinusM => 0b1010011,
+        Comp::MMinusD => 0b1000111,
+        Comp::DAndM => 0b1000000,
+        Comp::DOrM => 0b1010101,
+        Comp::Extended(_) => return None,
+    };
+    Some(0b111 << 7 | bits)
 }
 
-impl Parser<'_> {
-    pub fn new<'a>() -> Parser<'a> {
-        let mut comp_bits = HashMap::new();
-        comp_bits.insert("0".to_string(), "0101010");
-        comp_bits.insert("1".to_string(), "0111111");
-        comp_bits.insert("-1".to_string(), "0111010");
-        comp_bits.insert("D".to_string(), "0001100");
-        comp_bits.insert("A".to_string(), "0110000");
-        comp_bits.insert("!D".to_string(), "0001101");
-        comp_bits.insert("!A".to_string(), "0110001");
-        comp_bits.insert("-D".to_string(), "0001111");
-        comp_bits.insert("-A".to_string(), "0110011");
-        comp_bits.insert("D+1".to_string(), "0011111");
-        comp_bits.insert("A+1".to_string(), "0110111");
-        comp_bits.insert("D-1".to_string(), "0001110");
-        comp_bits.insert("A-1".to_string(), "0110010");
-        comp_bits.insert("D+A".to_string(), "0000010");
-        comp_bits.insert("D-A".to_string(), "0010011");
-        comp_bits.insert("A-D".to_string(), "0000111");
-        comp_bits.insert("D&A".to_string(), "0000000");
-        comp_bits.insert("D|A".to_string(), "0010101");
-        comp_bits.insert("M".to_string(), "1110000");
-        comp_bits.insert("!M".to_string(), "1110001");
-        comp_bits.insert("-M".to_string(), "1110011");
-        comp_bits.insert("M+1".to_string(), "1110111");
-        comp_bits.insert("M-1".to_string(), "1110010");
-        comp_bits.insert("D+M".to_string(), "1000010");
-        comp_bits.insert("D-M".to_string(), "1010011");
-        comp_bits.insert("M-D".to_string(), "1000111");
-        comp_bits.insert("D&M".to_string(), "1000000");
-        comp_bits.insert("D|M".to_string(), "1010101");
-
-
-        let mut jump_bits = HashMap::new();
-        jump_bits.insert("null".to_string(), "000");
-        jump_bits.insert("JGT".to_string(), "001");
-        jump_bits.insert("JEQ".to_string(), "010");
-        jump_bits.insert("JGE".to_string(), "011");
-        jump_bits.insert("JLT".to_string(), "100");
-        jump_bits.insert("JNE".to_string(), "101");
-        jump_bits.insert("JLE".to_string(), "110");
-        jump_bits.insert("JMP".to_string(), "111");
-
-        let mut dest_bits = HashMap::new();
-        dest_bits.insert("null".to_string(), "000");
-        dest_bits.insert("M".to_string(), "001");
-        dest_bits.insert("D".to_string(), "010");
-        dest_bits.insert("MD".to_string(), "011");
-        dest_bits.insert("A".to_string(), "100");
-        dest_bits.insert("AM".to_string(), "101");
-        dest_bits.insert("AD".to_string(), "110");
-        dest_bits.insert("AMD".to_string(), "111");
+/// Bits 5..3 of a C-instruction.
+pub fn encode_dest(dest: Dest) -> u16 {
+    match dest {
+        Dest::Null => 0b000,
+        Dest::M => 0b001,
+        Dest::D => 0b010,
+        Dest::MD => 0b011,
+        Dest::A => 0b100,
+        Dest::AM => 0b101,
+        Dest::AD => 0b110,
+        Dest::AMD => 0b111,
+    }
+}
+
+/// Bits 2..0 of a C-instruction.
+pub fn encode_jump(jump: Jump) -> u16 {
+    match jump {
+        Jump::Null => 0b000,
+        Jump::JGT => 0b001,
+        Jump::JEQ => 0b010,
+        Jump::JGE => 0b011,
+        Jump::JLT => 0b100,
+        Jump::JNE => 0b101,
+        Jump::JLE => 0b110,
+        Jump::JMP => 0b111,
+    }
+}
 
+#[derive(Debug)]
+pub struct Parser {
+    symbol_table: HashMap<String, Symbol>,
+    warnings: Vec<AssembleError>,
+    config: Config,
+    /// Names declared with `.extern`.
+    imports: HashSet<String>,
+    /// Labels named by `.global`, in order.
+    exports: Vec<String>,
+    /// Hands out RAM, first to `.var` and `.data` blocks, then to variables.
+    allocator: Allocator,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
         let mut symbol_table = HashMap::new();
-        symbol_table.insert("SP".to_string(), 0);
-        symbol_table.insert("LCL".to_string(), 1);
-        symbol_table.insert("ARG".to_string(), 2);
-        symbol_table.insert("THIS".to_string(), 3);
-        symbol_table.insert("THAT".to_string(), 4);
-        symbol_table.insert("R0".to_string(), 0);
-        symbol_table.insert("R1".to_string(), 1);
-        symbol_table.insert("R2".to_string(), 2);
-        symbol_table.insert("R3".to_string(), 3);
-        symbol_table.insert("R4".to_string(), 4);
-        symbol_table.insert("R5".to_string(), 5);
-        symbol_table.insert("R6".to_string(), 6);
-        symbol_table.insert("R7".to_string(), 7);
-        symbol_table.insert("R8".to_string(), 8);
-        symbol_table.insert("R9".to_string(), 9);
-        symbol_table.insert("R10".to_string(), 10);
-        symbol_table.insert("R11".to_string(), 11);
-        symbol_table.insert("R12".to_string(), 12);
-        symbol_table.insert("R13".to_string(), 13);
-        symbol_table.insert("R14".to_string(), 14);
-        symbol_table.insert("R15".to_string(), 15);
-        symbol_table.insert("SCREEN".to_string(), 16384);
-        symbol_table.insert("KBD".to_string(), 24576);
+        symbol_table.insert("SP".to_string(), Symbol::new(0, SymbolKind::Predefined));
+        symbol_table.insert("LCL".to_string(), Symbol::new(1, SymbolKind::Predefined));
+        symbol_table.insert("ARG".to_string(), Symbol::new(2, SymbolKind::Predefined));
+        symbol_table.insert("THIS".to_string(), Symbol::new(3, SymbolKind::Predefined));
+        symbol_table.insert("THAT".to_string(), Symbol::new(4, SymbolKind::Predefined));
+        symbol_table.insert("R0".to_string(), Symbol::new(0, SymbolKind::Predefined));
+        symbol_table.insert("R1".to_string(), Symbol::new(1, SymbolKind::Predefined));
+        symbol_table.insert("R2".to_string(), Symbol::new(2, SymbolKind::Predefined));
+        symbol_table.insert("R3".to_string(), Symbol::new(3, SymbolKind::Predefined));
+        symbol_table.insert("R4".to_string(), Symbol::new(4, SymbolKind::Predefined));
+        symbol_table.insert("R5".to_string(), Symbol::new(5, SymbolKind::Predefined));
+        symbol_table.insert("R6".to_string(), Symbol::new(6, SymbolKind::Predefined));
+        symbol_table.insert("R7".to_string(), Symbol::new(7, SymbolKind::Predefined));
+        symbol_table.insert("R8".to_string(), Symbol::new(8, SymbolKind::Predefined));
+        symbol_table.insert("R9".to_string(), Symbol::new(9, SymbolKind::Predefined));
+        symbol_table.insert("R10".to_string(), Symbol::new(10, SymbolKind::Predefined));
+        symbol_table.insert("R11".to_string(), Symbol::new(11, SymbolKind::Predefined));
+        symbol_table.insert("R12".to_string(), Symbol::new(12, SymbolKind::Predefined));
+        symbol_table.insert("R13".to_string(), Symbol::new(13, SymbolKind::Predefined));
+        symbol_table.insert("R14".to_string(), Symbol::new(14, SymbolKind::Predefined));
+        symbol_table.insert("R15".to_string(), Symbol::new(15, SymbolKind::Predefined));
+        symbol_table.insert("SCREEN".to_string(), Symbol::new(16384, SymbolKind::Predefined));
+        symbol_table.insert("KBD".to_string(), Symbol::new(24576, SymbolKind::Predefined));
 
+        let config = Config::default();
         Parser {
-            comp_bits,
-            jump_bits,
-            dest_bits,
             symbol_table,
+            warnings: Vec::new(),
+            allocator: Allocator::new(&config),
+            config,
+            imports: HashSet::new(),
+            exports: Vec::new(),
         }
     }
 
-    pub fn parse(&mut self, tokens: &Vec<Token>) -> Vec<String> {
-        let mut ram_address = 16;
-        let mut bitcode = Vec::new();
-        let mut opcode = String::new();
-        for token in tokens.into_iter() {
-            match token.get_token() {
-                Instruction::LInstruction(_) => {}
-                Instruction::AInstruction(a_token) => {
-                    if a_token.chars().nth(1).unwrap().is_numeric() {
-                        let mut addr = format!("{:b}", a_token.replace("@", "")
-                            .trim().parse::<u16>().unwrap());
-                        for _ in 0..(16 - addr.len()) {
-                            addr.insert(0, '0');
-                        }
-                        opcode.push_str(&addr);
-                    } else {
-                        // The tokens is a variable
-                        opcode.push('0');
-                        let var = a_token.replace("@", "").trim().to_string();
-                        if self.contains_symbol(&var) {
-                            let bits = format!("{:0>15b}", self.get_address(&var));
-                            opcode.push_str(&bits)
-                        } else {
-                            self.insert_symbol(&var, ram_address);
-                            let bits = format!("{:0>15b}", self.get_address(&var));
-                            opcode.push_str(&bits);
-                            ram_address += 1;
+    /// Assembles for the machine described by `config` instead of the
+    /// standard Hack computer. A ROM size above [`ROM_SIZE`] is clamped to it.
+    pub fn with_config(mut self, mut config: Config) -> Parser {
+        config.rom_size = config.rom_size.min(ROM_SIZE);
+        if !config.standard_symbols {
+            self.symbol_table
+                .retain(|_, symbol| symbol.kind != SymbolKind::Predefined);
+        }
+        for (name, address) in &config.symbols {
+            self.insert_symbol(name, *address, SymbolKind::Predefined);
+        }
+        self.allocator = Allocator::new(&config);
+        self.config = config;
+        self
+    }
+
+    /// Sets how many instructions fit in ROM, for machines with less than
+    /// the standard 32K words. Sizes above [`ROM_SIZE`] are clamped to it.
+    pub fn with_rom_size(mut self, rom_size: usize) -> Parser {
+        self.config.rom_size = rom_size.min(ROM_SIZE);
+        self
+    }
+
+    /// Sets the highest RAM address variables may be allocated at before
+    /// `parse` warns. Defaults to the last address below `SCREEN`.
+    pub fn with_ram_limit(mut self, ram_limit: u16) -> Parser {
+        self.config.ram_limit = ram_limit;
+        self
+    }
+
+    /// Encodes `tokens` into Hack machine code, allocating RAM for every
+    /// symbol that is not predefined, a label or a constant. Fails if the
+    /// program does not fit in ROM, and warns once variables pass the RAM limit.
+    pub fn parse(&mut self, tokens: &[Token]) -> Result<Vec<u16>, Vec<AssembleError>> {
+        let mut allocator = self.allocator.clone();
+        let mut words = Vec::new();
+        let mut errors = Vec::new();
+        for token in tokens {
+            let word = match token.get_token() {
+                Instruction::LInstruction(_) | Instruction::Directive(_) => continue,
+                Instruction::AInstruction(AValue::Literal(value)) => *value,
+                Instruction::AInstruction(AValue::Symbol(var)) => {
+                    match self.resolve(var, token, &mut allocator) {
+                        Ok(address) => address,
+                        Err(message) => {
+                            errors.push(token.error(message, 1, var.chars().count()));
+                            continue;
                         }
                     }
                 }
-                Instruction::CInstruction(c_token) => {
-                    opcode.push_str("111");
-                    let comp_bit = self.get_comp_bits(c_token);
-                    let dest_bit = self.get_dest_bits(c_token);
-                    let jump_bit = self.get_jump_bits(c_token);
-                    opcode = opcode + &comp_bit + &dest_bit + &jump_bit;
+                Instruction::AInstruction(AValue::Expression(expr)) => match self.eval(expr) {
+                    Ok(value) => value,
+                    Err(message) => {
+                        errors.push(token.error(message, 1, token.width() - 1));
+                        continue;
+                    }
+                },
+                Instruction::CInstruction { dest, comp, jump } => {
+                    match self.encode_c_instruction(token, *dest, *comp, *jump, &mut errors) {
+                        Some(word) => word,
+                        None => continue,
+                    }
                 }
+            };
+            if words.len() == self.config.rom_size {
+                errors.push(self.rom_overflow(tokens, token));
             }
-            if opcode != "" {
-                opcode.push_str("\n");
-                bitcode.push(opcode.to_string());
+            words.push(word);
+        }
+
+        self.allocator = allocator;
+        if errors.is_empty() {
+            Ok(words)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The error for `tokens` not fitting in ROM, reported at `token`, the
+    /// first instruction past the end.
+    fn rom_overflow(&self, tokens: &[Token], token: &Token) -> AssembleError {
+        let size = tokens
+            .iter()
+            .filter(|token| token.emits_word())
+            .count();
+        token.error(
+            format!(
+                "program needs {} words of ROM but only {} are available",
+                size, self.config.rom_size
+            ),
+            0,
+            token.width(),
+        )
+    }
+
+    /// Looks up the address `var` refers to in `token`. The symbol is either
+    /// a label, a constant or a variable, which `allocator` places in RAM on
+    /// first use.
+    fn resolve(
+        &mut self,
+        var: &str,
+        token: &Token,
+        allocator: &mut Allocator,
+    ) -> Result<u16, String> {
+        if self.imports.contains(var) {
+            return Err(format!(
+                "'{}' is declared with '.extern'; assemble with --object and link it",
+                var
+            ));
+        }
+        if !self.contains_symbol(var) {
+            if !self.config.implicit_variables {
+                return Err(format!(
+                    "undefined symbol '{}'; declare it with '.var' or enable implicit variables",
+                    var
+                ));
+            }
+            let allocation = allocator
+                .allocate(&self.config, 1)
+                .ok_or_else(|| format!("no RAM left to allocate variable '{}'", var))?;
+            if allocation.past_limit {
+                let message = format!(
+                    "variable '{}' is allocated at {}, past the RAM limit of {}",
+                    var, allocation.address, self.config.ram_limit
+                );
+                let note = format!(
+                    "variables are allocated upward from address {}",
+                    self.config.variable_base
+                );
+                let warning = token.error(message, 1, var.chars().count());
+                self.warnings.push(warning.with_note(note).into_warning());
+            }
+            self.insert_symbol(var, allocation.address, SymbolKind::Variable);
+        }
+
+        let address = self.get_address(var);
+        if address > MAX_ADDRESS {
+            return Err(format!(
+                "'{}' is at address {}, which does not fit in 15 bits (0..={})",
+                var, address, MAX_ADDRESS
+            ));
+        }
+        Ok(address)
+    }
+
+    /// Encodes `tokens` into a relocatable [`Object`] for `hack_linker`.
+    /// Label references are relative to the start of the module, and
+    /// imported labels and variables are left for the linker to fill in.
+    pub fn parse_object(&mut self, tokens: &[Token]) -> Result<Object, Vec<AssembleError>> {
+        let mut object = Object::default();
+        let mut variables = HashSet::new();
+        let mut errors = Vec::new();
+        for token in tokens {
+            let (word, relocation) = match token.get_token() {
+                Instruction::Directive(Directive::Var { .. })
+                | Instruction::Directive(Directive::Data { .. }) => {
+                    errors.push(token.error(
+                        String::from("RAM blocks are not supported in object files"),
+                        0,
+                        token.width(),
+                    ));
+                    continue;
+                }
+                Instruction::LInstruction(_) | Instruction::Directive(_) => continue,
+                Instruction::AInstruction(AValue::Literal(value)) => (*value, None),
+                Instruction::AInstruction(AValue::Symbol(name)) => {
+                    match self.symbol_table.get(name.as_ref()) {
+                        Some(symbol) if symbol.kind == SymbolKind::Label => {
+                            (symbol.address, Some(RelocationKind::Label))
+                        }
+                        Some(symbol) => (symbol.address, None),
+                        None if self.imports.contains(name.as_ref()) => {
+                            (0, Some(RelocationKind::Import(name.to_string())))
+                        }
+                        None if !self.config.implicit_variables => {
+                            errors.push(token.error(
+                                format!(
+                                    "undefined symbol '{}'; implicit variables are disabled",
+                                    name
+                                ),
+                                1,
+                                name.chars().count(),
+                            ));
+                            continue;
+                        }
+                        None => {
+                            if variables.insert(name.as_ref()) {
+                                object.variables.push(name.to_string());
+                            }
+                            (0, Some(RelocationKind::Variable(name.to_string())))
+                        }
+                    }
+                }
+                Instruction::AInstruction(AValue::Expression(expr)) => {
+                    match self.eval_relocatable(expr) {
+                        Ok((value, true)) => (value, Some(RelocationKind::Label)),
+                        Ok((value, false)) => (value, None),
+                        Err(message) => {
+                            errors.push(token.error(message, 1, token.width() - 1));
+                            continue;
+                        }
+                    }
+                }
+                Instruction::CInstruction { dest, comp, jump } => {
+                    match self.encode_c_instruction(token, *dest, *comp, *jump, &mut errors) {
+                        Some(word) => (word, None),
+                        None => continue,
+                    }
+                }
+            };
+            if let Some(kind) = relocation {
+                object.relocations.push(Relocation {
+                    offset: object.words.len(),
+                    kind,
+                });
             }
-            opcode.clear()
+            object.words.push(word);
+        }
+
+        object.exports = self
+            .exports
+            .iter()
+            .map(|name| (name.clone(), self.get_address(name)))
+            .collect();
+        object.imports = self.imports.iter().cloned().collect();
+        object.imports.sort();
+
+        if errors.is_empty() {
+            Ok(object)
+        } else {
+            Err(errors)
         }
-        bitcode
     }
 
-    pub fn first_pass(&mut self, tokens: &Vec<Token>) {
-        let mut program_counter = 0;
+    /// Assigns every label its ROM address, then evaluates `.equ` constants
+    /// in order so they are known before `parse` allocates any variables.
+    /// `.var` and `.data` blocks are then placed in RAM in order, ahead of
+    /// the implicit variables. A name can only be defined once, and never as
+    /// a predefined symbol; definitions are checked in source order, so a
+    /// duplicate is reported where it comes second. Fails if the program
+    /// does not fit in ROM.
+    pub fn first_pass(&mut self, tokens: &[Token]) -> Result<(), Vec<AssembleError>> {
+        let mut program_counter: usize = 0;
+        let mut constants = Vec::new();
+        let mut blocks = Vec::new();
+        let mut globals = Vec::new();
+        let mut definitions = Definitions::new(&self.symbol_table);
+        let mut errors = Vec::new();
         for token in tokens {
             match token.get_token() {
                 Instruction::LInstruction(label) => {
-                    let label = label.strip_prefix("(").unwrap()
-                        .strip_suffix(")").unwrap();
-                    self.insert_symbol(label, program_counter);
+                    // Past the end of ROM the program is reported as too big instead
+                    if self.check_definition(label, token, &mut definitions, &mut errors)
+                        && program_counter <= self.config.rom_size
+                    {
+                        self.insert_symbol(label, program_counter as u16, SymbolKind::Label);
+                    }
+                }
+                Instruction::Directive(Directive::Constant { name, value }) => {
+                    if self.check_definition(name, token, &mut definitions, &mut errors) {
+                        constants.push((token, name, value));
+                    }
+                }
+                Instruction::Directive(Directive::Extern(name)) => {
+                    if self.check_definition(name, token, &mut definitions, &mut errors) {
+                        self.imports.insert(name.clone());
+                    }
+                }
+                Instruction::Directive(Directive::Global(name)) => globals.push((token, name)),
+                Instruction::Directive(Directive::Var { name, size }) => {
+                    if self.check_definition(name, token, &mut definitions, &mut errors) {
+                        blocks.push((token, name, Ok(size)));
+                    }
+                }
+                Instruction::Directive(Directive::Data { name, values }) => {
+                    if self.check_definition(name, token, &mut definitions, &mut errors) {
+                        blocks.push((token, name, Err(values.len())));
+                    }
+                }
+                Instruction::Directive(_) => {}
+                _ => {
+                    if program_counter == self.config.rom_size {
+                        errors.push(self.rom_overflow(tokens, token));
+                    }
+                    program_counter += 1;
                 }
-                _ => program_counter += 1,
             }
         }
-    }
 
-    fn get_comp_bits(&self, token: &String) -> String {
-        if token.contains(";") {
-            let mut bit = token.split(";");
-            return String::from(*self.comp_bits.get(bit.nth(0).unwrap()).unwrap());
+        for (token, name, value) in constants {
+            match self.eval(value) {
+                Ok(value) => self.insert_symbol(name, value, SymbolKind::Constant),
+                Err(message) => errors.push(token.error(message, 0, token.width())),
+            }
+        }
+
+        for (token, name, size) in blocks {
+            let size = match size {
+                Ok(size) => match self.eval(size) {
+                    Ok(0) => Err(format!("'{}' must reserve at least one word", name)),
+                    result => result,
+                },
+                Err(count) => Ok(count as u16),
+            };
+            let allocation = size.and_then(|size| {
+                self.allocator
+                    .allocate(&self.config, size)
+                    .map(|allocation| (allocation, size))
+                    .ok_or_else(|| format!("no RAM left to reserve {} words for '{}'", size, name))
+            });
+            match allocation {
+                Ok((allocation, size)) => {
+                    if allocation.past_limit {
+                        let message = format!(
+                            "'{}' is allocated at {}, past the RAM limit of {}",
+                            name, allocation.address, self.config.ram_limit
+                        );
+                        self.warnings.push(token.error(message, 0, token.width()).into_warning());
+                    }
+                    let symbol = Symbol::new(allocation.address, SymbolKind::Variable);
+                    self.symbol_table.insert(name.clone(), symbol.with_size(size));
+                }
+                Err(message) => errors.push(token.error(message, 0, token.width())),
+            }
+        }
+
+        for (token, name) in globals {
+            match self.symbol_table.get(name) {
+                Some(symbol) if symbol.kind == SymbolKind::Label => {
+                    if !self.exports.contains(name) {
+                        self.exports.push(name.clone());
+                    }
+                }
+                _ => errors.push(token.error(
+                    format!("'{}' is not a label defined in this file", name),
+                    0,
+                    token.width(),
+                )),
+            }
         }
 
-        let mut bit = token.split("=");
-        String::from(*self.comp_bits.get(
-            bit.nth(1).unwrap())
-            .unwrap_or_else(|| panic!("{:?}", self.symbol_table)))
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
-    fn get_dest_bits(&self, token: &String) -> String {
-        if token.contains(";") {
-            return String::from(*self.dest_bits.get("null").unwrap());
+    /// Checks that the label or constant `name` declared by `token` is new,
+    /// recording it in `definitions`, or adds an error to `errors`. Symbols
+    /// are case-sensitive, so a name that differs from another only in case
+    /// is allowed but warned about.
+    fn check_definition<'a>(
+        &mut self,
+        name: &'a str,
+        token: &'a Token,
+        definitions: &mut Definitions<'a>,
+        errors: &mut Vec<AssembleError>,
+    ) -> bool {
+        let (offset, width) = match token.get_token() {
+            Instruction::LInstruction(_) => (1, name.chars().count()),
+            _ => (0, token.width()),
+        };
+        if let Some(first) = definitions.tokens.get(name) {
+            errors.push(
+                token
+                    .error(format!("'{}' is already defined", name), offset, width)
+                    .with_note(format!(
+                        "first defined at {}:{}:{}",
+                        first.file(),
+                        first.line(),
+                        first.column()
+                    )),
+            );
+            return false;
+        }
+        if self.contains_symbol(name) {
+            errors.push(token.error(
+                format!("'{}' is a predefined symbol and cannot be redefined", name),
+                offset,
+                width,
+            ));
+            return false;
         }
 
-        let mut bit = token.split("=");
-        String::from(*self.dest_bits.get(bit.nth(0).unwrap()).unwrap())
+        let folded = name.to_ascii_uppercase();
+        if let Some((similar, kind)) = definitions.folded.get(&folded) {
+            let kind = match kind {
+                SymbolKind::Predefined => "predefined symbol",
+                kind => kind.name(),
+            };
+            self.warnings.push(
+                token
+                    .error(
+                        format!(
+                            "'{}' differs from {} '{}' only in case; symbols are case-sensitive",
+                            name, kind, similar
+                        ),
+                        offset,
+                        width,
+                    )
+                    .into_warning(),
+            );
+        } else {
+            let kind = match token.get_token() {
+                Instruction::Directive(Directive::Constant { .. }) => SymbolKind::Constant,
+                Instruction::Directive(Directive::Var { .. })
+                | Instruction::Directive(Directive::Data { .. }) => SymbolKind::Variable,
+                _ => SymbolKind::Label,
+            };
+            definitions.folded.insert(folded, (String::from(name), kind));
+        }
+        definitions.tokens.insert(name, token);
+        true
     }
 
-    fn get_jump_bits(&self, token: &String) -> String {
-        if token.contains("=") {
-            return String::from(*self.jump_bits.get("null").unwrap());
+    /// Evaluates a constant expression against the symbols known so far.
+    /// The result must be loadable by an A-instruction.
+    fn eval(&self, expr: &Expr) -> Result<u16, String> {
+        let lookup = |name: &str| self.symbol_table.get(name).map(|s| s.address as i32);
+        let value = expr.eval(&lookup)?;
+        if (0..=MAX_ADDRESS as i32).contains(&value) {
+            Ok(value as u16)
+        } else {
+            Err(format!(
+                "'{}' evaluates to {}, which does not fit in 15 bits (0..={})",
+                expr, value, MAX_ADDRESS
+            ))
         }
+    }
+
+    /// Evaluates an expression in a relocatable module, where labels are
+    /// offsets from the start of the module. Returns whether the linker has to
+    /// add the module's address, which is the case when the labels in the
+    /// expression add up to exactly one, as in `TABLE+2`.
+    fn eval_relocatable(&self, expr: &Expr) -> Result<(u16, bool), String> {
+        if let Some(import) = expr.symbols().into_iter().find(|name| self.imports.contains(*name)) {
+            return Err(format!(
+                "imported label '{}' cannot be used in an expression",
+                import
+            ));
+        }
+        // Expressions are linear, so moving every label up by one changes the
+        // value by the signed number of labels in it
+        let value = |shift: i32| {
+            let lookup = |name: &str| {
+                self.symbol_table.get(name).map(|symbol| match symbol.kind {
+                    SymbolKind::Label => symbol.address as i32 + shift,
+                    _ => symbol.address as i32,
+                })
+            };
+            expr.eval(&lookup)
+        };
+        let relocatable = match value(1)? - value(0)? {
+            0 => false,
+            1 => true,
+            _ => {
+                return Err(format!(
+                    "'{}' cannot be relocated; it must add at most one label",
+                    expr
+                ))
+            }
+        };
+        Ok((self.eval(expr)?, relocatable))
+    }
+
+    /// Encodes a C-instruction, or adds an error to `errors` for an
+    /// extended comp that is not in the instruction set.
+    fn encode_c_instruction(
+        &self,
+        token: &Token,
+        dest: Dest,
+        comp: Comp,
+        jump: Jump,
+        errors: &mut Vec<AssembleError>,
+    ) -> Option<u16> {
+        let extended = || self.config.instruction_set.encode(comp);
+        let comp_bits = match encode_comp(comp).or_else(extended) {
+            Some(bits) => bits,
+            None => {
+                let (offset, width) = token.comp_span();
+                let message = format!("unknown comp '{}'", comp.mnemonic());
+                let error = token.error(message, offset, width);
+                errors.push(match extension_of(comp) {
+                    Some(extension) => error.with_note(format!(
+                        "'{}' is part of the {} extension; enable it with \
+                         'extensions = {}' in the machine config",
+                        comp.mnemonic(),
+                        extension,
+                        extension
+                    )),
+                    None => error,
+                });
+                return None;
+            }
+        };
+        Some(comp_bits << 6 | encode_dest(dest) << 3 | encode_jump(jump))
+    }
+
+    /// The extended comps this parser accepts besides the standard ones.
+    pub fn get_instruction_set(&self) -> &InstructionSet {
+        &self.config.instruction_set
+    }
 
-        let mut bit = token.split(";");
-        String::from(*self.jump_bits.get(bit.nth(1).unwrap()).unwrap())
+    /// Problems found so far that do not stop assembly.
+    pub fn get_warnings(&self) -> &Vec<AssembleError> {
+        &self.warnings
     }
 
-    fn get_address(&self, symbol: &str) -> &i32 {
-        let addr = self.symbol_table.get(symbol).unwrap();
-        addr
+    /// The predefined symbols, labels and variables resolved so far.
+    pub fn get_symbol_table(&self) -> &HashMap<String, Symbol> {
+        &self.symbol_table
+    }
+
+    fn get_address(&self, symbol: &str) -> u16 {
+        self.symbol_table[symbol].address
     }
 
     fn contains_symbol(&self, symbol: &str) -> bool {
         self.symbol_table.contains_key(symbol)
     }
 
-    fn insert_symbol(&mut self, symbol: &str, address: i32) {
-        self.symbol_table.insert(symbol.to_string(), address);
+    fn insert_symbol(&mut self, symbol: &str, address: u16, kind: SymbolKind) {
+        self.symbol_table.insert(symbol.to_string(), Symbol::new(address, kind));
+    }
+}
+
+/// The labels and constants seen so far in `Parser::first_pass`.
+struct Definitions<'a> {
+    tokens: HashMap<&'a str, &'a Token<'a>>,
+    /// Every defined or predefined name and its kind, by its upper-case form.
+    folded: HashMap<String, (String, SymbolKind)>,
+}
+
+impl<'a> Definitions<'a> {
+    fn new(symbol_table: &HashMap<String, Symbol>) -> Definitions<'a> {
+        Definitions {
+            tokens: HashMap::new(),
+            folded: symbol_table
+                .iter()
+                .map(|(name, symbol)| (name.to_ascii_uppercase(), (name.clone(), symbol.kind)))
+                .collect(),
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
     }
-}
\ No newline at end of file
+}
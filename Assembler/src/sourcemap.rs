@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+
+use crate::json::Value;
+use crate::Assembly;
+
+/// The version of the source map format, for tools to check.
+const VERSION: usize = 1;
+
+/// Writes a JSON source map of `assembly` for debuggers and emulators: for
+/// every ROM address, the file, line and column of the instruction it was
+/// assembled from. Files are listed once under `sources` and referred to by
+/// index. A word expanded from a macro points into the macro's body and also
+/// lists the calls it came through under `expansions`, innermost first, so
+/// the last one is the line in the caller's own code.
+///
+/// ```text
+/// {
+///   "version": 1,
+///   "sources": ["main.asm", "stack.asm"],
+///   "words": [
+///     {"address":0,"source":0,"line":3,"column":5},
+///     {"address":1,"source":1,"line":2,"column":5,"expansions":[{"macro":"PUSH_D","source":0,"line":4,"column":5}]}
+///   ]
+/// }
+/// ```
+pub fn write_source_map(assembly: &Assembly, writer: &mut dyn Write) -> io::Result<()> {
+    let mut sources: Vec<&str> = Vec::new();
+    let mut source_index = |file| match sources.iter().position(|source| *source == file) {
+        Some(index) => index,
+        None => {
+            sources.push(file);
+            sources.len() - 1
+        }
+    };
+
+    let mut words = Vec::new();
+    let tokens = assembly.tokens.iter().filter(|token| token.emits_word());
+    for (address, token) in tokens.enumerate() {
+        let mut members = vec![
+            ("address", Value::from(address)),
+            ("source", Value::from(source_index(token.file()))),
+            ("line", Value::from(token.line())),
+            ("column", Value::from(token.column())),
+        ];
+        let mut expansions = Vec::new();
+        let mut expansion = token.expansion();
+        while let Some(call) = expansion {
+            expansions.push(Value::object(vec![
+                ("macro", Value::from(call.macro_name.as_str())),
+                ("source", Value::from(source_index(&call.file))),
+                ("line", Value::from(call.line)),
+                ("column", Value::from(call.column)),
+            ]));
+            expansion = call.parent.as_deref();
+        }
+        if !expansions.is_empty() {
+            members.push(("expansions", Value::Array(expansions)));
+        }
+        words.push(Value::object(members));
+    }
+
+    let sources = Value::Array(sources.into_iter().map(Value::from).collect());
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"version\": {},", VERSION)?;
+    writeln!(writer, "  \"sources\": {},", sources)?;
+    writeln!(writer, "  \"words\": [")?;
+    for (index, word) in words.iter().enumerate() {
+        let separator = if index + 1 < words.len() { "," } else { "" };
+        writeln!(writer, "    {}{}", word, separator)?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolKind {
+    /// Built into the platform, such as `SP`, `R0` or `SCREEN`.
+    Predefined,
+    /// Declared with `(NAME)`; the address is in ROM.
+    Label,
+    /// Allocated on first use; the address is in RAM.
+    Variable,
+    /// Defined with `.equ` or `.define`.
+    Constant,
+}
+
+impl SymbolKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymbolKind::Predefined => "predefined",
+            SymbolKind::Label => "label",
+            SymbolKind::Variable => "variable",
+            SymbolKind::Constant => "constant",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub address: u16,
+    pub kind: SymbolKind,
+    /// The number of RAM words reserved at `address`, which is more than one
+    /// for arrays declared with `.var` or `.data`.
+    pub size: u16,
+}
+
+impl Symbol {
+    pub fn new(address: u16, kind: SymbolKind) -> Symbol {
+        Symbol {
+            address,
+            kind,
+            size: 1,
+        }
+    }
+
+    pub fn with_size(mut self, size: u16) -> Symbol {
+        self.size = size;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolFormat {
+    /// One `<kind> <name> <address>` entry per line, as read by
+    /// [`read_symbol_map`](crate::disassembler::read_symbol_map). Arrays
+    /// have their size as a fourth field.
+    Text,
+    Json,
+}
+
+impl SymbolFormat {
+    pub fn from_name(name: &str) -> Option<SymbolFormat> {
+        match name {
+            "text" => Some(SymbolFormat::Text),
+            "json" => Some(SymbolFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SymbolFormat::Text => "sym",
+            SymbolFormat::Json => "sym.json",
+        }
+    }
+}
+
+/// Writes `symbols` grouped by kind and ordered by address within each kind.
+pub fn write_symbols(
+    symbols: &HashMap<String, Symbol>,
+    format: SymbolFormat,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let mut sorted: Vec<_> = symbols.iter().collect();
+    sorted.sort_by(|(a_name, a), (b_name, b)| {
+        (a.kind, a.address, a_name).cmp(&(b.kind, b.address, b_name))
+    });
+
+    match format {
+        SymbolFormat::Text => {
+            for (name, symbol) in sorted {
+                write!(writer, "{} {} {}", symbol.kind.name(), name, symbol.address)?;
+                if symbol.size > 1 {
+                    write!(writer, " {}", symbol.size)?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        SymbolFormat::Json => {
+            writeln!(writer, "{{")?;
+            writeln!(writer, "  \"symbols\": [")?;
+            for (index, (name, symbol)) in sorted.iter().enumerate() {
+                let separator = if index + 1 < sorted.len() { "," } else { "" };
+                let mut members = vec![
+                    ("name", Value::from(name.as_str())),
+                    ("kind", Value::from(symbol.kind.name())),
+                    ("address", Value::from(symbol.address as usize)),
+                ];
+                if symbol.size > 1 {
+                    members.push(("size", Value::from(symbol.size as usize)));
+                }
+                writeln!(writer, "    {}{}", Value::object(members), separator)?;
+            }
+            writeln!(writer, "  ]")?;
+            writeln!(writer, "}}")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,255 @@
+//! What the language server asks of a document: definitions, references,
+//! renames, hovers and completions, including symbols that come from macro
+//! calls and included files.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use hack_assembler::analysis::{Analysis, Location, Position, Range};
+use hack_assembler::config::Config;
+use hack_assembler::error::Severity;
+use hack_assembler::parser::Parser;
+
+const LIB: &str = "\
+(LIB_INIT)
+    @R0
+    M=0
+";
+
+const MAIN: &str = "\
+.include \"lib.asm\"
+.macro JUMP target
+    @\\target
+    0;JMP
+.endm
+(START)
+    @counter
+    M=M+1
+    JUMP START // again
+    @LIB_INIT
+    D=A
+";
+
+/// A fresh directory named after `test` holding `lib.asm`, and the analysis
+/// of `MAIN` saved next to it.
+fn analyse(test: &str) -> (Analysis, PathBuf) {
+    let dir = env::temp_dir().join(format!("hack_analysis_{}_{}", test, process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.asm"), LIB).unwrap();
+    let main = dir.join("main.asm");
+    let analysis = Analysis::new(MAIN, main.to_str().unwrap(), Parser::new());
+    (analysis, dir)
+}
+
+fn at(line: usize, character: usize) -> Position {
+    Position { line, character }
+}
+
+fn range(line: usize, start: usize, end: usize) -> Range {
+    Range {
+        start: at(line, start),
+        end: at(line, end),
+    }
+}
+
+#[test]
+fn document_without_problems() {
+    let (analysis, _) = analyse("clean");
+    assert!(
+        analysis.diagnostics().is_empty(),
+        "{:?}",
+        analysis.diagnostics()
+    );
+}
+
+#[test]
+fn diagnostics() {
+    let analysis = Analysis::new("@R0\nD=D*A\n", "test.asm", Parser::new());
+    let diagnostics = analysis.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].message, "unknown comp 'D*A'");
+    assert_eq!(diagnostics[0].range, range(1, 2, 5));
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+
+    // Symbols still resolve while a line does not lex
+    let analysis = Analysis::new("(LOOP)\n(R0)\n@LOOP\n", "test.asm", Parser::new());
+    assert_eq!(
+        analysis.diagnostics()[0].message,
+        "'R0' is a predefined symbol and cannot be redefined"
+    );
+    assert_eq!(analysis.diagnostics()[0].range, range(1, 1, 3));
+    assert_eq!(analysis.symbol_at(at(2, 2)), Some("LOOP"));
+}
+
+#[test]
+fn definition_in_the_document() {
+    let (analysis, dir) = analyse("definition");
+    // `START` in the macro call
+    assert_eq!(analysis.symbol_at(at(8, 11)), Some("START"));
+    assert_eq!(
+        analysis.definition(at(8, 11)),
+        Some(Location {
+            file: String::from(dir.join("main.asm").to_str().unwrap()),
+            range: range(5, 1, 6),
+        })
+    );
+    // Names in comments and macro parameters are not symbols
+    assert_eq!(analysis.symbol_at(at(8, 18)), None);
+    assert_eq!(analysis.symbol_at(at(2, 7)), None);
+}
+
+#[test]
+fn definition_in_an_included_file() {
+    let (analysis, dir) = analyse("included");
+    assert_eq!(
+        analysis.definition(at(9, 6)),
+        Some(Location {
+            file: String::from(dir.join("lib.asm").to_str().unwrap()),
+            range: range(0, 1, 9),
+        })
+    );
+}
+
+#[test]
+fn references() {
+    let (analysis, _) = analyse("references");
+    let lines = |include_definition| -> Vec<Range> {
+        analysis
+            .references(at(5, 3), include_definition)
+            .into_iter()
+            .map(|location| location.range)
+            .collect()
+    };
+    assert_eq!(lines(true), [range(5, 1, 6), range(8, 9, 14)]);
+    assert_eq!(lines(false), [range(8, 9, 14)]);
+    // Nothing to find outside a symbol
+    assert!(analysis.references(at(7, 5), true).is_empty());
+}
+
+#[test]
+fn rename() {
+    let (analysis, _) = analyse("rename");
+    assert_eq!(
+        analysis.rename(at(8, 10), "BEGIN"),
+        Ok(vec![
+            (range(5, 1, 6), String::from("BEGIN")),
+            (range(8, 9, 14), String::from("BEGIN")),
+        ])
+    );
+    assert_eq!(
+        analysis.rename(at(9, 6), "INIT"),
+        Err(String::from("'LIB_INIT' is defined in another file"))
+    );
+    assert_eq!(
+        analysis.rename(at(6, 6), "total"),
+        Err(String::from(
+            "only labels can be renamed, and 'counter' is not one"
+        ))
+    );
+    assert_eq!(
+        analysis.rename(at(5, 1), "counter"),
+        Err(String::from("'counter' is already defined"))
+    );
+    assert_eq!(
+        analysis.rename(at(5, 1), "1ST"),
+        Err(String::from("'1ST' is not a valid symbol name"))
+    );
+    assert_eq!(
+        analysis.rename(at(7, 5), "X"),
+        Err(String::from("no symbol here"))
+    );
+}
+
+#[test]
+fn hover() {
+    let (analysis, _) = analyse("hover");
+    // `LIB_INIT` and its two instructions come first
+    assert_eq!(
+        analysis.hover(at(6, 7)),
+        Some((
+            String::from(
+                "variable `counter`: RAM address 16 (0x0010)\n\n\
+                 ```\n    2  0000000000010000  0010\n```"
+            ),
+            Some(range(6, 5, 12))
+        ))
+    );
+    // A macro call shows the words it expands to
+    assert_eq!(
+        analysis.hover(at(8, 11)),
+        Some((
+            String::from(
+                "label `START`: ROM address 2 (0x0002)\n\n\
+                 ```\n    4  0000000000000010  0002\n    5  1110101010000111  EA87\n```"
+            ),
+            Some(range(8, 9, 14))
+        ))
+    );
+    assert_eq!(
+        analysis.hover(at(7, 5)),
+        Some((
+            String::from("```\n    3  1111110111001000  FDC8\n```"),
+            None
+        ))
+    );
+    // Macro definitions emit nothing themselves
+    assert_eq!(analysis.hover(at(3, 5)), None);
+}
+
+#[test]
+fn completions() {
+    let (analysis, _) = analyse("completions");
+    let symbols = analysis.completions(at(6, 5));
+    for expected in [
+        ("counter", "variable"),
+        ("START", "label"),
+        ("LIB_INIT", "label"),
+    ] {
+        assert!(
+            symbols.contains(&(String::from(expected.0), expected.1)),
+            "{:?}",
+            symbols
+        );
+    }
+    assert!(symbols.contains(&(String::from("SP"), "predefined")));
+
+    let names = |position| -> Vec<String> {
+        analysis
+            .completions(position)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    };
+    // After `;` in `0;JMP`
+    assert_eq!(
+        names(at(3, 6)),
+        ["JEQ", "JGE", "JGT", "JLE", "JLT", "JMP", "JNE"]
+    );
+    // After `=` in `M=M+1`
+    let comps = names(at(7, 6));
+    assert!(comps.contains(&String::from("D+1")));
+    assert!(!comps.contains(&String::from("AM=")));
+    // At the start of an instruction, comps and dests
+    let start = analysis.completions(at(7, 4));
+    assert!(start.contains(&(String::from("AM="), "dest")));
+    assert!(start.contains(&(String::from("0"), "comp")));
+    assert!(!start.iter().any(|(name, _)| name.starts_with("null")));
+    // Nothing inside a comment or a label
+    assert!(analysis.completions(at(8, 20)).is_empty());
+    assert!(analysis.completions(at(5, 2)).is_empty());
+}
+
+#[test]
+fn extended_comps_are_completed() {
+    let config = Config::parse("extensions = shift\n", "m.cfg").unwrap();
+    let parser = Parser::new().with_config(config);
+    let analysis = Analysis::new("D=D<<\n", "test.asm", parser);
+    let comps = analysis.completions(at(0, 2));
+    assert!(
+        comps.contains(&(String::from("D<<"), "comp")),
+        "{:?}",
+        comps
+    );
+}
@@ -0,0 +1,187 @@
+//! The `main` and `hack_linker` binaries: where they write, what they print
+//! and how they exit.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+
+const MAIN: &str = env!("CARGO_BIN_EXE_main");
+const LINKER: &str = env!("CARGO_BIN_EXE_hack_linker");
+
+/// A fresh, empty directory named after `test`.
+fn dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hack_cli_{}_{}", test, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(program: &str, args: &[&str]) -> Output {
+    Command::new(program).args(args).output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn stdin_to_stdout() {
+    let mut child = Command::new(MAIN)
+        .args(["--format", "hex", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"@5\nD=A\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0005\nec10\n");
+}
+
+#[test]
+fn output_path_and_side_files() {
+    let dir = dir("output");
+    let input = dir.join("prog.asm");
+    fs::write(&input, "(LOOP)\n@LOOP\n0;JMP\n").unwrap();
+    let output = dir.join("out.bin");
+    let result = run(
+        MAIN,
+        &[
+            "-o",
+            path(&output),
+            "--format",
+            "bin-be",
+            "--symbols",
+            "--listing",
+            path(&input),
+        ],
+    );
+    assert!(result.status.success(), "{}", stderr(&result));
+    assert_eq!(fs::read(&output).unwrap(), [0x00, 0x00, 0xea, 0x87]);
+    // Side files are named after the output
+    let symbols = fs::read_to_string(dir.join("out.sym")).unwrap();
+    assert!(symbols.contains("LOOP"), "{}", symbols);
+    assert!(dir.join("out.lst").exists());
+    assert!(!dir.join("prog.hack").exists());
+}
+
+#[test]
+fn every_input_is_assembled() {
+    let dir = dir("inputs");
+    let first = dir.join("first.asm");
+    let second = dir.join("second.asm");
+    fs::write(&first, "@1\n").unwrap();
+    fs::write(&second, "@2\n").unwrap();
+    let result = run(MAIN, &[path(&first), path(&second)]);
+    assert!(result.status.success(), "{}", stderr(&result));
+    assert_eq!(
+        fs::read_to_string(dir.join("first.hack")).unwrap(),
+        "0000000000000001\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("second.hack")).unwrap(),
+        "0000000000000010\n"
+    );
+}
+
+#[test]
+fn a_failing_input_does_not_stop_the_others() {
+    let dir = dir("failing");
+    let bad = dir.join("bad.asm");
+    let good = dir.join("good.asm");
+    fs::write(&bad, "D=X\n").unwrap();
+    fs::write(&good, "@1\n").unwrap();
+    let result = run(MAIN, &[path(&bad), path(&good)]);
+    assert_eq!(result.status.code(), Some(1));
+    let messages = stderr(&result);
+    assert!(messages.contains("unknown comp 'X'"), "{}", messages);
+    assert!(
+        messages.contains("bad.asm: 1 error(s) found, no output written"),
+        "{}",
+        messages
+    );
+    assert!(!dir.join("bad.hack").exists());
+    assert!(dir.join("good.hack").exists());
+
+    let missing = dir.join("missing.asm");
+    let result = run(MAIN, &[path(&missing)]);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(stderr(&result).starts_with("error: could not read"));
+}
+
+#[test]
+fn warnings_as_errors() {
+    let dir = dir("werror");
+    let input = dir.join("vars.asm");
+    fs::write(&input, "@a\n@b\n").unwrap();
+    let result = run(MAIN, &["--ram-limit", "16", path(&input)]);
+    assert!(result.status.success(), "{}", stderr(&result));
+    assert!(stderr(&result).contains("past the RAM limit of 16"));
+    fs::remove_file(dir.join("vars.hack")).unwrap();
+
+    let result = run(MAIN, &["--ram-limit", "16", "--werror", path(&input)]);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(
+        stderr(&result).contains("1 warning(s) treated as errors, no output written"),
+        "{}",
+        stderr(&result)
+    );
+    assert!(!dir.join("vars.hack").exists());
+}
+
+#[test]
+fn invalid_usage() {
+    let usage = |args: &[&str], message: &str| {
+        let result = run(MAIN, args);
+        assert_eq!(result.status.code(), Some(2), "{:?}", args);
+        let stderr = stderr(&result);
+        assert!(
+            stderr.starts_with(&format!("error: {}\n", message)),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("usage: main"), "{}", stderr);
+    };
+    usage(&[], "no input files");
+    usage(&["--bogus", "a.asm"], "unknown option '--bogus'");
+    usage(&["a.asm", "-o"], "-o expects a value");
+    usage(
+        &["-o", "x.hack", "a.asm", "b.asm"],
+        "-o cannot be used with multiple input files",
+    );
+    usage(
+        &["--format", "elf", "a.asm"],
+        "unknown format 'elf', expected one of: hack, hex, bin-le, bin-be, ihex, \
+         readmemb, readmemh, logisim",
+    );
+    usage(
+        &["--rom-size", "0", "a.asm"],
+        "--rom-size expects 1 to 32768 words, got '0'",
+    );
+
+    let result = run(MAIN, &["--help"]);
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stdout).starts_with("usage: main"));
+}
+
+#[test]
+fn linker_usage() {
+    let result = run(LINKER, &["--format", "elf", "a.hobj"]);
+    assert_eq!(result.status.code(), Some(2));
+    let stderr = stderr(&result);
+    assert!(
+        stderr.starts_with(
+            "error: unknown format 'elf', expected one of: hack, hex, bin-le, bin-be, \
+             ihex, readmemb, readmemh, logisim\nusage: hack_linker"
+        ),
+        "{}",
+        stderr
+    );
+    assert_eq!(run(LINKER, &[]).status.code(), Some(2));
+}
@@ -0,0 +1,139 @@
+//! Regression tests for comment stripping, whitespace and line endings in
+//! the lexer.
+
+use hack_assembler::assemble_str;
+use hack_assembler::error::AssembleError;
+use hack_assembler::lexer::{source_lines, Instruction, LineContent};
+
+fn words(code: &str) -> Vec<u16> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+fn errors(code: &str) -> Vec<AssembleError> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => panic!("assembled {:?} into {:?}", code, assembly.words),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn comment_directly_after_instruction() {
+    assert_eq!(words("D=M// note\n"), words("D=M\n"));
+    assert_eq!(words("D=M//note\n"), words("D=M\n"));
+    assert_eq!(words("@SP// note\n"), words("@SP\n"));
+    assert_eq!(words("0;JMP//\n"), words("0;JMP\n"));
+}
+
+#[test]
+fn comment_after_spaces_and_tabs() {
+    assert_eq!(words("D=M // note\n"), words("D=M\n"));
+    assert_eq!(words("D=M    // note\n"), words("D=M\n"));
+    assert_eq!(words("D=M\t// note\n"), words("D=M\n"));
+}
+
+#[test]
+fn comment_after_label() {
+    let assembly = assemble_str("(LOOP)// top\n@LOOP\n0;JMP\n", "test.asm").unwrap();
+    assert_eq!(assembly.symbols["LOOP"].address, 0);
+    assert_eq!(assembly.words.len(), 2);
+}
+
+#[test]
+fn comment_only_lines() {
+    assert_eq!(
+        words("//\n// note\n   // indented\n\t//tab\n///\nD=M\n"),
+        words("D=M\n")
+    );
+}
+
+#[test]
+fn comment_inside_comment() {
+    assert_eq!(words("D=M // a // b\n"), words("D=M\n"));
+}
+
+#[test]
+fn single_slash_is_an_error_not_a_comment() {
+    for code in ["/\n", "/ comment\n", "D=M / note\n", "/x//y\n", "D=M/\n"] {
+        let errors = errors(code);
+        assert_eq!(errors.len(), 1, "{:?}", code);
+    }
+}
+
+#[test]
+fn tabs_inside_instructions() {
+    assert_eq!(words("\tD\t=\tD+M\t;\tJGT\n"), words("D=D+M;JGT\n"));
+    assert_eq!(words("\t@\tSP\n"), words("@SP\n"));
+    assert_eq!(words(".equ\tN\t5\n@N\n"), words("@5\n"));
+}
+
+#[test]
+fn spaces_inside_instructions() {
+    assert_eq!(words("AM = M + 1 ; JGT\n"), words("AM=M+1;JGT\n"));
+    assert_eq!(words("D = A\n"), words("D=A\n"));
+    assert_eq!(words("0 ; JMP\n"), words("0;JMP\n"));
+    assert_eq!(words("  M = - 1  \n"), words("M=-1\n"));
+    assert_eq!(words("( LOOP )\n@ LOOP\n"), words("(LOOP)\n@LOOP\n"));
+}
+
+#[test]
+fn blank_lines_take_no_address() {
+    let code = "\n   \n\t\n@1\n\n \t \n(NEXT)\n\n@NEXT\n\n";
+    let assembly = assemble_str(code, "test.asm").unwrap();
+    assert_eq!(assembly.words, [1, 1]);
+    assert_eq!(assembly.symbols["NEXT"].address, 1);
+    // Lines are still counted for errors
+    let error = &errors("\n\t\n  \n D=X\n")[0];
+    assert_eq!((error.line, error.column), (4, 4));
+}
+
+#[test]
+fn crlf_line_endings() {
+    let unix = words("@2\nD=A\n(END)\n@END\n0;JMP\n");
+    assert_eq!(words("@2\r\nD=A\r\n(END)\r\n@END\r\n0;JMP\r\n"), unix);
+    assert_eq!(words("@2\r\nD=A // two\r\n(END)\r\n@END\r\n0;JMP\r"), unix);
+}
+
+#[test]
+fn error_columns_count_from_the_source() {
+    let error = &errors("D=M\n    D=X // bad\n")[0];
+    assert_eq!((error.line, error.column, error.width), (2, 7, 1));
+
+    let error = &errors("\t\tD=X// bad\n")[0];
+    assert_eq!((error.line, error.column, error.width), (1, 5, 1));
+
+    let error = &errors("@SP\r\n  D=X\r\n")[0];
+    assert_eq!((error.line, error.column), (2, 5));
+    assert_eq!(error.source_line, "  D=X");
+}
+
+#[test]
+fn caret_keeps_tabs() {
+    let rendered = errors("\tD=X\n")[0].to_string();
+    assert!(rendered.contains("1 | \tD=X\n  | \t  ^"), "{}", rendered);
+}
+
+#[test]
+fn source_lines_keep_comments() {
+    let lines = source_lines("D=M// note\n\n  // only\n@SP\r\n", "test.asm").unwrap();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0].code, "D=M");
+    assert_eq!(lines[0].comment.as_deref(), Some(" note"));
+    assert_eq!(lines[1].comment, None);
+    assert_eq!(lines[2].comment.as_deref(), Some(" only"));
+    assert!(lines[2].indented);
+    assert!(matches!(
+        lines[3].content,
+        LineContent::Instruction(Instruction::AInstruction(_))
+    ));
+    assert_eq!(lines[3].code, "@SP");
+}
+
+#[test]
+fn slashes_in_include_path_are_not_a_comment() {
+    let lines = source_lines(".include \"lib//math.asm\" // math\n", "test.asm").unwrap();
+    assert_eq!(lines[0].code, ".include \"lib//math.asm\"");
+    assert_eq!(lines[0].comment.as_deref(), Some(" math"));
+}
@@ -0,0 +1,195 @@
+//! Machine config files and how they change RAM allocation.
+
+use hack_assembler::assemble_with;
+use hack_assembler::config::{Allocation, Allocator, Config};
+use hack_assembler::error::AssembleError;
+use hack_assembler::parser::Parser;
+use hack_assembler::symbols::SymbolKind;
+
+fn config(code: &str) -> Config {
+    match Config::parse(code, "machine.cfg") {
+        Ok(config) => config,
+        Err(errors) => panic!("invalid config {:?}: {:?}", code, errors),
+    }
+}
+
+fn errors(code: &str) -> Vec<AssembleError> {
+    match Config::parse(code, "machine.cfg") {
+        Ok(config) => panic!("accepted {:?} as {:?}", code, config),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn settings_and_symbols() {
+    let config = config(
+        "\
+# A smaller machine
+rom_size = 0x1000
+ram_limit = 2047     # end of RAM
+variable_base = 0b100000
+reserve = 40..47
+reserve = 0x100 .. 0x1ff
+implicit_variables = false
+standard_symbols = false
+
+[symbols]
+LED = 0x2001
+BUTTONS = 8194
+",
+    );
+    assert_eq!(config.rom_size, 4096);
+    assert_eq!(config.ram_limit, 2047);
+    assert_eq!(config.variable_base, 32);
+    assert_eq!(config.reserved, [40..=47, 256..=511]);
+    assert!(!config.implicit_variables);
+    assert!(!config.standard_symbols);
+    assert_eq!(
+        config.symbols,
+        [
+            (String::from("LED"), 0x2001),
+            (String::from("BUTTONS"), 8194)
+        ]
+    );
+    assert_eq!(Config::parse("", "empty.cfg"), Ok(Config::default()));
+}
+
+#[test]
+fn config_symbols_are_predefined() {
+    let config = config("standard_symbols = false\n[symbols]\nLED = 24577\n");
+    let parser = Parser::new().with_config(config);
+    let assembly = assemble_with("@LED\nM=1\n@SP\n", "test.asm", parser).unwrap();
+    assert_eq!(assembly.words[0], 24577);
+    assert_eq!(assembly.symbols["LED"].kind, SymbolKind::Predefined);
+    // Without the standard symbols, `SP` is just a variable
+    assert_eq!(assembly.symbols["SP"].kind, SymbolKind::Variable);
+}
+
+#[test]
+fn invalid_settings() {
+    let cases = [
+        ("speed = 3", "unknown setting 'speed'"),
+        ("rom_size = 0", "rom_size must be 1 to 32768, got '0'"),
+        (
+            "rom_size = 40000",
+            "rom_size must be 1 to 32768, got '40000'",
+        ),
+        ("ram_limit = lots", "invalid address 'lots'"),
+        ("variable_base = 0x10000", "invalid address '0x10000'"),
+        ("reserve = 10", "expected 'start..end', got '10'"),
+        ("reserve = 20..10", "empty range '20..10'"),
+        (
+            "implicit_variables = yes",
+            "expected 'true' or 'false', got 'yes'",
+        ),
+        ("extensions = float", "unknown extension 'float'"),
+        ("rom_size", "expected 'key = value'"),
+        ("[memory]", "unknown section '[memory]'"),
+        ("[symbols]\n1LED = 5", "invalid symbol name '1LED'"),
+        ("[symbols]\nLED = -1", "invalid address '-1'"),
+    ];
+    for (code, message) in cases {
+        let errors = errors(code);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].message, message, "{:?}", code);
+    }
+}
+
+#[test]
+fn every_error_points_at_its_line() {
+    let errors = errors("rom_size = 100\n  speed = 3 # fast\n\n[symbols]\nLED = x\n");
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(errors[0].file, "machine.cfg");
+    assert_eq!(
+        (errors[0].line, errors[0].column, errors[0].width),
+        (2, 3, "speed = 3".len())
+    );
+    assert_eq!(errors[0].source_line, "  speed = 3 # fast");
+    assert_eq!(errors[1].line, 5);
+}
+
+#[test]
+fn allocation_skips_reserved_ranges() {
+    let config = Config {
+        reserved: vec![17..=19, 21..=21, 24..=25],
+        ..Config::default()
+    };
+    let mut allocator = Allocator::new(&config);
+    let mut next = |size| allocator.allocate(&config, size).unwrap().address;
+    assert_eq!(next(1), 16);
+    assert_eq!(next(1), 20);
+    assert_eq!(next(1), 22);
+    // Three words from 23 would overlap 24..=25
+    assert_eq!(next(3), 26);
+    assert_eq!(next(1), 29);
+
+    // The base itself may be reserved
+    let config = Config {
+        reserved: vec![10..=20],
+        ..Config::default()
+    };
+    assert_eq!(
+        Allocator::new(&config)
+            .allocate(&config, 1)
+            .unwrap()
+            .address,
+        21
+    );
+}
+
+#[test]
+fn allocation_ends_at_the_top_of_ram() {
+    let config = Config {
+        variable_base: 0x7ffe,
+        ..Config::default()
+    };
+    let mut allocator = Allocator::new(&config);
+    assert_eq!(allocator.allocate(&config, 3), None);
+    assert_eq!(allocator.allocate(&config, 2).unwrap().address, 0x7ffe);
+    assert_eq!(allocator.allocate(&config, 1), None);
+}
+
+#[test]
+fn past_the_limit_is_reported_once() {
+    let config = Config {
+        ram_limit: 17,
+        ..Config::default()
+    };
+    let mut allocator = Allocator::new(&config);
+    let past: Vec<bool> = (0..4)
+        .map(|_| allocator.allocate(&config, 1).unwrap())
+        .map(|allocation: Allocation| allocation.past_limit)
+        .collect();
+    assert_eq!(past, [false, false, true, false]);
+
+    let parser = Parser::new().with_config(config);
+    let assembly = assemble_with("@a\n@b\n@c\n@d\n@c\n", "test.asm", parser).unwrap();
+    assert_eq!(assembly.warnings.len(), 1, "{:?}", assembly.warnings);
+    assert_eq!(
+        assembly.warnings[0].message,
+        "variable 'c' is allocated at 18, past the RAM limit of 17"
+    );
+    assert_eq!(assembly.warnings[0].line, 3);
+    assert_eq!(assembly.symbols["d"].address, 19);
+}
+
+#[test]
+fn implicit_variables_disabled() {
+    let config = config("implicit_variables = false\n");
+    let parser = Parser::new().with_config(config.clone());
+    let errors = assemble_with("@x\nM=0\n@y\n", "test.asm", parser).unwrap_err();
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        "undefined symbol 'x'; declare it with '.var' or enable implicit variables"
+    );
+    assert_eq!((errors[0].line, errors[1].line), (1, 3));
+
+    // Declared blocks, labels and predefined symbols still work
+    let parser = Parser::new().with_config(config);
+    let code = ".var x 1\n(LOOP)\n@x\nM=0\n@SP\n@LOOP\n0;JMP\n";
+    assert_eq!(
+        assemble_with(code, "test.asm", parser).unwrap().words,
+        [16, 0xea88, 0, 0, 0xea87]
+    );
+}
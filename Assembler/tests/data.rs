@@ -0,0 +1,94 @@
+//! `.var` and `.data` blocks: where they are placed in RAM, and the prologue
+//! that stores the `.data` values before the program runs.
+
+use hack_assembler::assemble_str;
+use hack_assembler::symbols::SymbolKind;
+
+fn words(code: &str) -> Vec<u16> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+const M_ZERO: u16 = 0xea88; // M=0
+const M_ONE: u16 = 0xefc8; // M=1
+const M_MINUS_ONE: u16 = 0xee88; // M=-1
+const D_A: u16 = 0xec10; // D=A
+const D_NOT_A: u16 = 0xec50; // D=!A
+const M_D: u16 = 0xe308; // M=D
+
+#[test]
+fn blocks_are_allocated_in_order_before_variables() {
+    let code = "@i\nM=0\n.var buffer 3\n.var flag\n.data table 7, 8\n@buffer+2\n@table\n";
+    let assembly = assemble_str(code, "test.asm").unwrap();
+    let symbol = |name: &str| {
+        let symbol = &assembly.symbols[name];
+        assert_eq!(symbol.kind, SymbolKind::Variable);
+        (symbol.address, symbol.size)
+    };
+    assert_eq!(symbol("buffer"), (16, 3));
+    assert_eq!(symbol("flag"), (19, 1));
+    assert_eq!(symbol("table"), (20, 2));
+    // Implicit variables come after every block, even ones declared later
+    assert_eq!(symbol("i"), (22, 1));
+    assert_eq!(
+        assembly.words[assembly.words.len() - 4..],
+        [22, M_ZERO, 18, 20]
+    );
+}
+
+#[test]
+fn values_stored_directly() {
+    assert_eq!(
+        words(".data t 0, 1, 0xffff\n"),
+        [16, M_ZERO, 17, M_ONE, 18, M_MINUS_ONE]
+    );
+    // -1 is 0xffff in two's complement
+    assert_eq!(words(".data t -1\n"), [16, M_MINUS_ONE]);
+}
+
+#[test]
+fn values_loaded_through_a() {
+    assert_eq!(
+        words(".data t 2, 32767\n"),
+        [2, D_A, 16, M_D, 32767, D_A, 17, M_D]
+    );
+}
+
+#[test]
+fn values_loaded_through_not_a() {
+    // Values with the top bit set cannot be an A-instruction, so their
+    // complement is loaded instead
+    assert_eq!(
+        words(".data t -2, 32768, 40000\n"),
+        [1, D_NOT_A, 16, M_D, 32767, D_NOT_A, 17, M_D, 25535, D_NOT_A, 18, M_D]
+    );
+    assert_eq!(words(".data t -32768\n"), [32767, D_NOT_A, 16, M_D]);
+}
+
+#[test]
+fn prologue_comes_before_the_program() {
+    let code = "(START)\n@START\n0;JMP\n.data a 5\n.data b 0\n";
+    // Labels count the prologue's instructions
+    assert_eq!(words(code), [5, D_A, 16, M_D, 17, M_ZERO, 6, 0xea87]);
+}
+
+#[test]
+fn invalid_blocks() {
+    let message = |code| {
+        assemble_str(code, "test.asm").unwrap_err()[0]
+            .message
+            .clone()
+    };
+    assert_eq!(message(".var b 0\n"), "'b' must reserve at least one word");
+    assert_eq!(
+        message(".data t 65536\n"),
+        "'65536' does not fit in 16 bits"
+    );
+    assert_eq!(message(".data t\n"), "missing values for 't'");
+    assert_eq!(
+        message(".var a 0x7ff1\n"),
+        "no RAM left to reserve 32753 words for 'a'"
+    );
+}
@@ -0,0 +1,233 @@
+// Bootstrap Code
+@256
+D=A
+@SP
+M=D
+// call function Sys.init
+@RETURN_LABEL1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push local 0
+@LCL
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push argument 0
+@ARG
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push this 0
+@THIS
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push that 0
+@THAT
+D=M
+@0
+A=D+A
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@SP
+D=M
+@5
+D=D-A
+@0
+D=D-A
+@ARG
+M=D
+@SP
+D=M
+@LCL
+M=D
+@Sys.init
+0;JMP
+(RETURN_LABEL1)
+// Bootstrap code ends
+
+// function Sys.init 0
+(Sys.init)
+// push constant 5
+@5
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// pop static 0
+@Sum.0
+D=A
+@R13
+M=D
+@SP
+M=M-1
+A=M
+D=M
+@R13
+A=M
+M=D
+// label LOOP
+(LOOP)
+// push static 0
+@Sum.0
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push constant 0
+@0
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// eq
+@SP
+M=M-1
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+D=M-D
+@LABEL1
+D;JEQ
+@SP
+A=M
+M=0
+@ENDLABEL1
+0;JMP
+(LABEL1)
+@SP
+A=M
+M=-1
+(ENDLABEL1)
+@SP
+M=M+1
+// if-goto DONE
+@SP
+AM=M-1
+D=M
+A=A-1
+@DONE
+D;JNE
+// push static 1
+@Sum.1
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push static 0
+@Sum.0
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// add
+@SP
+M=M-1
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+M=D+M
+@SP
+M=M+1
+// pop static 1
+@Sum.1
+D=A
+@R13
+M=D
+@SP
+M=M-1
+A=M
+D=M
+@R13
+A=M
+M=D
+// push static 0
+@Sum.0
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// push constant 1
+@1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+// sub
+@SP
+M=M-1
+A=M
+D=M
+@SP
+M=M-1
+@SP
+A=M
+M=M-D
+@SP
+M=M+1
+// pop static 0
+@Sum.0
+D=A
+@R13
+M=D
+@SP
+M=M-1
+A=M
+D=M
+@R13
+A=M
+M=D
+// goto LOOP
+@LOOP
+0;JMP
+// label DONE
+(DONE)
+// goto DONE
+@DONE
+0;JMP
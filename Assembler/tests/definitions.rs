@@ -0,0 +1,123 @@
+//! Defining a name twice, redefining a predefined symbol and names that
+//! differ only in case.
+
+use hack_assembler::config::Config;
+use hack_assembler::error::{AssembleError, Severity};
+use hack_assembler::lint::Options;
+use hack_assembler::parser::Parser;
+use hack_assembler::{assemble_str, assemble_with, lint_with};
+
+fn errors(code: &str) -> Vec<AssembleError> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => panic!("assembled {:?} into {:?}", code, assembly.words),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn duplicate_label() {
+    let errors = errors("(LOOP)\n@LOOP\n  (LOOP)\n0;JMP\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let error = &errors[0];
+    assert_eq!(error.message, "'LOOP' is already defined");
+    // The name inside the parentheses is underlined
+    assert_eq!((error.line, error.column, error.width), (3, 4, 4));
+    assert_eq!(error.notes, ["first defined at test.asm:1:1"]);
+}
+
+#[test]
+fn duplicates_across_kinds() {
+    let cases = [
+        ".equ N 5\n(N)\n",
+        "(N)\n.equ N 5\n",
+        ".var N\n.data N 1\n",
+        "(N)\n.var N 2\n",
+        ".extern N\n(N)\n",
+    ];
+    for code in cases {
+        let errors = errors(code);
+        assert_eq!(errors.len(), 1, "{:?}: {:?}", code, errors);
+        assert_eq!(errors[0].message, "'N' is already defined", "{:?}", code);
+        assert_eq!(errors[0].line, 2, "{:?}", code);
+        assert_eq!(errors[0].notes, ["first defined at test.asm:1:1"]);
+    }
+}
+
+#[test]
+fn predefined_symbols_cannot_be_redefined() {
+    let error = &errors("(SP)\n@SP\n")[0];
+    assert_eq!(
+        error.message,
+        "'SP' is a predefined symbol and cannot be redefined"
+    );
+    assert_eq!((error.line, error.column, error.width), (1, 2, 2));
+    assert!(error.notes.is_empty());
+
+    for code in [".equ R0 3\n", ".var KBD\n", ".data SCREEN 1\n"] {
+        let errors = errors(code);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(
+            errors[0]
+                .message
+                .ends_with("is a predefined symbol and cannot be redefined"),
+            "{:?}",
+            errors[0]
+        );
+    }
+
+    // Without the standard symbols the names are free
+    let config = Config::parse("standard_symbols = false\n", "machine.cfg").unwrap();
+    let parser = Parser::new().with_config(config);
+    let assembly = assemble_with("@1\n(SP)\n@SP\n", "test.asm", parser).unwrap();
+    assert_eq!(assembly.words, [1, 1]);
+}
+
+#[test]
+fn names_differing_only_in_case_are_warned_about() {
+    let code = "(LOOP)\n@loop\n0;JMP\n(loop)\n@LOOP\n0;JMP\n";
+    let assembly = assemble_str(code, "test.asm").unwrap();
+    assert_eq!(assembly.warnings.len(), 1, "{:?}", assembly.warnings);
+    let warning = &assembly.warnings[0];
+    assert_eq!(warning.severity, Severity::Warning);
+    assert_eq!(
+        warning.message,
+        "'loop' differs from label 'LOOP' only in case; symbols are case-sensitive"
+    );
+    assert_eq!((warning.line, warning.column, warning.width), (4, 2, 4));
+    // Both labels are still defined, each at its own address
+    assert_eq!(assembly.symbols["LOOP"].address, 0);
+    assert_eq!(assembly.symbols["loop"].address, 2);
+    assert_eq!(assembly.words, [2, 0xea87, 0, 0xea87]);
+
+    let warnings = |code| -> Vec<String> {
+        let assembly = assemble_str(code, "test.asm").unwrap();
+        assembly
+            .warnings
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    };
+    assert_eq!(
+        warnings("(screen)\n"),
+        ["'screen' differs from predefined symbol 'SCREEN' only in case; symbols are case-sensitive"]
+    );
+    assert_eq!(
+        warnings(".equ SIZE 4\n.var Size\n"),
+        ["'Size' differs from constant 'SIZE' only in case; symbols are case-sensitive"]
+    );
+}
+
+#[test]
+fn references_differing_only_in_case_are_linted() {
+    // Outside of --lint, `@loop` is just a new variable
+    let code = "(LOOP)\n@loop\nM=1\n@LOOP\n0;JMP\n";
+    assert!(assemble_str(code, "test.asm").unwrap().warnings.is_empty());
+
+    let assembly = lint_with(code, "test.asm", Parser::new(), Options::default()).unwrap();
+    assert_eq!(assembly.warnings.len(), 1, "{:?}", assembly.warnings);
+    assert_eq!(
+        assembly.warnings[0].message,
+        "'loop' is allocated as a variable, but looks like a misspelling of label 'LOOP'"
+    );
+    assert_eq!(assembly.warnings[0].line, 2);
+}
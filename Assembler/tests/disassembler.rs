@@ -0,0 +1,51 @@
+//! Disassembling with generated labels and assembling the result again must
+//! give back the same words.
+
+use hack_assembler::assemble_str;
+use hack_assembler::disassembler::Disassembler;
+
+fn words(code: &str) -> Vec<u16> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+/// Disassembles `words` with `--labels`, checks the source reassembles to
+/// the same words and returns it.
+fn round_trip(words: &[u16]) -> String {
+    let disassembly = Disassembler::new().label_jumps(true).disassemble(words);
+    assert!(disassembly.illegal.is_empty());
+    assert_eq!(
+        self::words(&disassembly.source),
+        words,
+        "{}",
+        disassembly.source
+    );
+    disassembly.source
+}
+
+#[test]
+fn program_asm() {
+    round_trip(&words(include_str!("../Program.asm")));
+}
+
+#[test]
+fn vm_translator_output() {
+    let source = round_trip(&words(include_str!("data/Sum.asm")));
+    assert!(source.contains("(L_"), "{}", source);
+}
+
+#[test]
+fn jump_targets_in_the_program_are_named() {
+    let source = round_trip(&words("(LOOP)\nD=D-1\n@LOOP\nD;JGT\n@4\n0;JMP\n"));
+    assert!(source.starts_with("(L_0000)\n"), "{}", source);
+    assert!(source.contains("@L_0000\n"), "{}", source);
+}
+
+#[test]
+fn jump_targets_past_the_end_keep_their_number() {
+    let source = round_trip(&words("@100\n0;JMP\n@4\nD;JEQ\n"));
+    assert!(!source.contains("L_"), "{}", source);
+    assert!(source.contains("@100\n"), "{}", source);
+}
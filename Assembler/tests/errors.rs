@@ -0,0 +1,46 @@
+//! Errors from every phase are reported together in one run.
+
+use hack_assembler::assemble_str;
+
+fn messages(code: &str) -> Vec<String> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => panic!("assembled {:?} into {:?}", code, assembly.words),
+        Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+    }
+}
+
+#[test]
+fn lexer_and_parser_errors_in_one_run() {
+    let code = "(LOOP)\nD=X\n(LOOP)\n@LOOP\n@40000-1\n@missing+1\n";
+    assert_eq!(
+        messages(code),
+        [
+            "unknown comp 'X'",
+            "'LOOP' is already defined",
+            "'40000-1' evaluates to 39999, which does not fit in 15 bits (0..=32767)",
+            "undefined symbol 'missing' in expression",
+        ]
+    );
+}
+
+#[test]
+fn rejected_definitions_do_not_cause_more_errors() {
+    let code = ".equ K 1+\n@K+1\n.define L\n@L-1\n.data T 1, x\n@T+1\n.var B 0x\n@B+1\n\
+                (END\n@END+1\n.equ M K+1\n@M\n";
+    assert_eq!(
+        messages(code),
+        [
+            "expected a number, symbol or '(' at end of expression",
+            "missing value for 'L'",
+            "'.data' values must be numbers",
+            "invalid number '0x'",
+            "missing ')' in label declaration '(END'",
+        ]
+    );
+}
+
+#[test]
+fn errors_after_the_first_phase_still_stop_assembly() {
+    assert_eq!(messages("(A)\n(A)\n@A\n"), ["'A' is already defined"]);
+    assert_eq!(messages("D=X\n@0\n"), ["unknown comp 'X'"]);
+}
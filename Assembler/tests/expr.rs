@@ -0,0 +1,162 @@
+//! Constant expressions, on their own and in A-instructions.
+
+use hack_assembler::assemble_str;
+use hack_assembler::expr::{BinaryOp, Expr};
+
+fn eval(text: &str) -> Result<i32, String> {
+    let lookup = |name: &str| match name {
+        "SCREEN" => Some(16384),
+        "KBD" => Some(24576),
+        "LIMIT" => Some(100),
+        _ => None,
+    };
+    let expr = Expr::parse(text).map_err(|error| error.message)?;
+    expr.eval(&lookup)
+}
+
+fn words(code: &str) -> Vec<u16> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+fn error(code: &str) -> String {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => panic!("assembled {:?} into {:?}", code, assembly.words),
+        Err(errors) => errors[0].message.clone(),
+    }
+}
+
+#[test]
+fn literals() {
+    assert_eq!(eval("42"), Ok(42));
+    assert_eq!(eval("0x4000"), Ok(16384));
+    assert_eq!(eval("0b101"), Ok(5));
+    assert_eq!(eval("  7  "), Ok(7));
+}
+
+#[test]
+fn operators_associate_left() {
+    assert_eq!(eval("10-3-2"), Ok(5));
+    assert_eq!(eval("10-3+2"), Ok(9));
+    assert_eq!(eval("1 + 2 - 4"), Ok(-1));
+}
+
+#[test]
+fn unary_minus_binds_tightest() {
+    assert_eq!(eval("-1+LIMIT"), Ok(99));
+    assert_eq!(eval("-(1+2)"), Ok(-3));
+    assert_eq!(eval("--5"), Ok(5));
+    assert_eq!(eval("2--5"), Ok(7));
+}
+
+#[test]
+fn parentheses() {
+    assert_eq!(eval("10-(3-2)"), Ok(9));
+    assert_eq!(eval("((KBD-1))"), Ok(24575));
+    assert_eq!(eval("10-(3+(2-1))"), Ok(6));
+    assert_eq!(eval("(1+2"), Err(String::from("unclosed '('")));
+}
+
+#[test]
+fn symbols() {
+    assert_eq!(eval("SCREEN+32"), Ok(16416));
+    assert_eq!(eval("KBD-SCREEN"), Ok(8192));
+    assert_eq!(
+        eval("MISSING+1"),
+        Err(String::from("undefined symbol 'MISSING' in expression"))
+    );
+    assert_eq!(
+        Expr::parse("KBD-SCREEN+LIMIT").unwrap().symbols(),
+        ["KBD", "SCREEN", "LIMIT"]
+    );
+}
+
+#[test]
+fn parsed_tree_and_display() {
+    let expr = Expr::parse("A-(B+1)").unwrap();
+    assert_eq!(
+        expr,
+        Expr::Binary(
+            BinaryOp::Sub,
+            Box::new(Expr::Symbol(String::from("A"))),
+            Box::new(Expr::Binary(
+                BinaryOp::Add,
+                Box::new(Expr::Symbol(String::from("B"))),
+                Box::new(Expr::Number(1)),
+            )),
+        )
+    );
+    assert_eq!(expr.to_string(), "A-(B+1)");
+    assert_eq!(Expr::parse("-(1+2)").unwrap().to_string(), "-(1+2)");
+}
+
+#[test]
+fn syntax_errors() {
+    let error = Expr::parse("1+").unwrap_err();
+    assert_eq!(error.offset, 2);
+    assert_eq!(
+        error.message,
+        "expected a number, symbol or '(' at end of expression"
+    );
+    // Only `+` and `-` are operators
+    assert!(Expr::parse("ROW*2").is_err());
+    let error = Expr::parse("1+*").unwrap_err();
+    assert_eq!((error.offset, error.width), (2, 1));
+    assert_eq!(
+        Expr::parse("0xZZ").unwrap_err().message,
+        "invalid number '0xZZ'"
+    );
+    assert_eq!(
+        Expr::parse("1 2").unwrap_err().message,
+        "unexpected trailing characters"
+    );
+}
+
+#[test]
+fn overflow() {
+    assert_eq!(eval("2147483647"), Ok(i32::MAX));
+    assert_eq!(
+        eval("2147483647+1"),
+        Err(String::from("arithmetic overflow in expression"))
+    );
+    assert_eq!(
+        eval("0-2147483647-2"),
+        Err(String::from("arithmetic overflow in expression"))
+    );
+    assert_eq!(
+        eval("-(0-2147483647-1)"),
+        Err(String::from("arithmetic overflow in expression"))
+    );
+    assert_eq!(
+        eval("2147483648"),
+        Err(String::from("invalid number '2147483648'"))
+    );
+}
+
+#[test]
+fn a_instruction_results_must_fit_in_15_bits() {
+    assert_eq!(
+        error("@-1\n"),
+        "'-1' evaluates to -1, which does not fit in 15 bits (0..=32767)"
+    );
+    assert_eq!(
+        error("@32767+1\n"),
+        "'32767+1' evaluates to 32768, which does not fit in 15 bits (0..=32767)"
+    );
+    assert_eq!(words(".equ LIMIT 100\n@-1+LIMIT\n"), [99]);
+    assert_eq!(words("@32768-1\n"), [32767]);
+    assert_eq!(words("@0-0\n"), [0]);
+}
+
+#[test]
+fn a_instructions_with_labels_and_constants() {
+    let code = ".equ ROW 32\n(START)\n@SCREEN+ROW\n@(KBD-1)\n@TABLE+2\n(TABLE)\n@START\n";
+    assert_eq!(words(code), [16416, 24575, 5, 0]);
+}
+
+#[test]
+fn symbols_in_expressions_are_not_variables() {
+    assert!(error("@counter+1\n").contains("undefined symbol 'counter'"));
+}
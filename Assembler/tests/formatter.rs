@@ -0,0 +1,111 @@
+//! `--fmt`: the canonical layout, and that formatting changes neither the
+//! words a program assembles to nor an already formatted file.
+
+use hack_assembler::assemble_str;
+use hack_assembler::formatter::format_str;
+
+/// `tests/data/Sum.vm` translated by `vm`.
+const VM_OUTPUT: &str = include_str!("data/Sum.asm");
+
+/// Macros, directives, odd spacing and comments in every position.
+const MIXED: &str = "\n\n\
+// Copies a word
+.macro COPY src, dst
+  @\\src
+D=M   // load
+      @\\dst
+  M=D
+.endm
+.equ   LIMIT   0x10 + 2
+.var buffer 4
+.data table 1, -2, 0x7fff
+.global START
+  (START)
+@ LIMIT
+  D = A+D ; JGT   // compare
+      // a lone comment
+
+
+
+      COPY   R0,R1
+@buffer + 1
+  M  =  -1
+(END)
+    @END
+0;JMP
+";
+
+fn format(code: &str) -> String {
+    match format_str(code, "test.asm") {
+        Ok(formatted) => formatted,
+        Err(errors) => panic!("failed to format {:?}: {:?}", code, errors),
+    }
+}
+
+fn words(code: &str) -> Vec<u16> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+/// Formatting `code` keeps its words, and formatting again changes nothing.
+fn assert_stable(code: &str) -> String {
+    let formatted = format(code);
+    assert_eq!(words(&formatted), words(code), "{}", formatted);
+    assert_eq!(format(&formatted), formatted);
+    formatted
+}
+
+#[test]
+fn vm_translator_output() {
+    assert_stable(VM_OUTPUT);
+}
+
+#[test]
+fn macros_and_directives() {
+    let formatted = assert_stable(MIXED);
+    assert_eq!(
+        formatted,
+        "\
+// Copies a word
+.macro COPY src, dst
+    @\\src
+    D=M // load
+    @\\dst
+    M=D
+.endm
+.equ LIMIT 0x10 + 2
+.var buffer 4
+.data table 1, -2, 0x7fff
+.global START
+(START)
+    @LIMIT
+    D=D+A;JGT // compare
+    // a lone comment
+
+    COPY R0, R1
+    @buffer+1
+    M=-1
+(END)
+    @END
+    0;JMP
+"
+    );
+}
+
+#[test]
+fn trailing_comments_are_aligned() {
+    let code = "@i // counter\nM=M+1 // next\n\nD=0 // alone\n";
+    assert_eq!(
+        assert_stable(code),
+        "    @i    // counter\n    M=M+1 // next\n\n    D=0 // alone\n"
+    );
+}
+
+#[test]
+fn files_with_errors_are_not_formatted() {
+    let errors = format_str("@R0\nD=X\n", "test.asm").unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].line, 2);
+}
@@ -0,0 +1,167 @@
+//! `.include`: finding the file, relative paths, cycles and where errors in
+//! included files are reported.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use hack_assembler::error::AssembleError;
+use hack_assembler::{assemble_str, Assembly};
+
+/// A fresh directory named after `test` holding `files`, given as
+/// `(path, code)` pairs relative to it.
+fn dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hack_include_{}_{}", test, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (path, code) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, code).unwrap();
+    }
+    dir
+}
+
+fn name(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// Assembles `main.asm` from `dir`.
+fn assemble(dir: &Path) -> Result<Assembly<'static>, Vec<AssembleError>> {
+    let main = dir.join("main.asm");
+    let code = fs::read_to_string(&main).unwrap();
+    assemble_str(&code, name(&main)).map(|assembly| assembly.into_owned())
+}
+
+#[test]
+fn included_code_is_spliced_in() {
+    let dir = dir(
+        "splice",
+        &[
+            ("main.asm", "@1\n.include \"lib.asm\"\n@INC\n0;JMP\n"),
+            (
+                "lib.asm",
+                "// increments R0\n.equ ONE 1\n(INC)\n@ONE\n@R0\nM=M+1\n",
+            ),
+        ],
+    );
+    let assembly = assemble(&dir).unwrap();
+    assert_eq!(assembly.words, [1, 1, 0, 0xfdc8, 1, 0xea87]);
+    assert_eq!(assembly.symbols["INC"].address, 1);
+    assert_eq!(assembly.symbols["ONE"].address, 1);
+}
+
+#[test]
+fn paths_are_relative_to_the_including_file() {
+    let dir = dir(
+        "relative",
+        &[
+            ("main.asm", ".include \"sub/a.asm\"\n"),
+            (
+                "sub/a.asm",
+                "@1\n.include \"b.asm\"\n.include \"../c.asm\"\n",
+            ),
+            ("sub/b.asm", "@2\n"),
+            ("c.asm", "@3\n"),
+            // Not the file `sub/a.asm` means
+            ("b.asm", "@4\n"),
+        ],
+    );
+    assert_eq!(assemble(&dir).unwrap().words, [1, 2, 3]);
+}
+
+#[test]
+fn a_file_can_be_included_twice() {
+    let dir = dir(
+        "twice",
+        &[
+            ("main.asm", ".include \"a.asm\"\n.include \"b.asm\"\n"),
+            ("a.asm", ".include \"common.asm\"\n"),
+            ("b.asm", ".include \"common.asm\"\n"),
+            ("common.asm", "D=D+1\n"),
+        ],
+    );
+    assert_eq!(assemble(&dir).unwrap().words, [0xe7d0, 0xe7d0]);
+}
+
+#[test]
+fn include_cycles() {
+    let dir = dir(
+        "cycle",
+        &[
+            ("main.asm", "@0\n.include \"a.asm\"\n"),
+            ("a.asm", ".include \"b.asm\"\n"),
+            ("b.asm", "@1\n.include \"a.asm\"\n"),
+        ],
+    );
+    let errors = assemble(&dir).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let (main, a, b) = (dir.join("main.asm"), dir.join("a.asm"), dir.join("b.asm"));
+    assert_eq!(
+        errors[0].message,
+        format!(
+            "include cycle: {} -> {} -> {} -> {}",
+            name(&main),
+            name(&a),
+            name(&b),
+            name(&a)
+        )
+    );
+    // Reported at the directive that closes the cycle
+    assert_eq!(errors[0].file, name(&b));
+    assert_eq!(errors[0].line, 2);
+
+    let dir = self::dir("self", &[("main.asm", ".include \"main.asm\"\n")]);
+    let main = dir.join("main.asm");
+    let errors = assemble(&dir).unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        format!("include cycle: {} -> {}", name(&main), name(&main))
+    );
+}
+
+#[test]
+fn errors_point_into_the_included_file() {
+    let dir = dir(
+        "errors",
+        &[
+            ("main.asm", "@0\n.include \"lib.asm\"\nD=Y\n"),
+            ("lib.asm", "// helpers\n@1\n  D=X\n"),
+        ],
+    );
+    let errors = assemble(&dir).unwrap_err();
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(errors[0].message, "unknown comp 'X'");
+    assert_eq!(errors[0].file, name(&dir.join("lib.asm")));
+    assert_eq!((errors[0].line, errors[0].column), (3, 5));
+    assert_eq!(errors[0].source_line, "  D=X");
+    // Lines after the include keep their own numbers
+    assert_eq!(errors[1].file, name(&dir.join("main.asm")));
+    assert_eq!(errors[1].line, 3);
+}
+
+#[test]
+fn missing_and_malformed_includes() {
+    let dir = dir("missing", &[("main.asm", "@0\n.include \"gone.asm\"\n")]);
+    let errors = assemble(&dir).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let prefix = format!("could not include {}: ", name(&dir.join("gone.asm")));
+    assert!(
+        errors[0].message.starts_with(&prefix),
+        "{}",
+        errors[0].message
+    );
+    assert_eq!(
+        (errors[0].file.as_str(), errors[0].line),
+        (name(&dir.join("main.asm")), 2)
+    );
+
+    for code in [".include lib.asm\n", ".include \"\"\n", ".include\n"] {
+        let errors = assemble_str(code, "test.asm").unwrap_err();
+        assert_eq!(
+            errors[0].message, "expected '.include \"file.asm\"'",
+            "{:?}",
+            code
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Extended instruction sets: describing them, assembling their comps and
+//! decoding them again.
+
+use hack_assembler::assemble_with;
+use hack_assembler::config::Config;
+use hack_assembler::disassembler::Disassembler;
+use hack_assembler::isa::InstructionSet;
+use hack_assembler::lexer::Comp;
+use hack_assembler::parser::Parser;
+
+const MULTIPLIER: &str = "\
+# A CPU with a multiplier
+[instructions]
+D*A = 0b1000000000
+D*M = 0x240
+";
+
+fn config(code: &str) -> Config {
+    match Config::parse(code, "machine.cfg") {
+        Ok(config) => config,
+        Err(errors) => panic!("invalid config {:?}: {:?}", code, errors),
+    }
+}
+
+fn words(code: &str, config: &Config) -> Vec<u16> {
+    let parser = Parser::new().with_config(config.clone());
+    match assemble_with(code, "test.asm", parser) {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+#[test]
+fn instructions_section() {
+    let config = config(MULTIPLIER);
+    assert_eq!(
+        config.instruction_set.get_comps(),
+        [
+            (Comp::extended("D*A").unwrap(), 0b10_0000_0000),
+            (Comp::extended("D*M").unwrap(), 0b10_0100_0000),
+        ]
+    );
+}
+
+#[test]
+fn extended_comps_assemble() {
+    let config = config(MULTIPLIER);
+    assert_eq!(
+        words("D=D*A\nAM=D*M;JGT\nD = D * M\n", &config),
+        [
+            0b1000_0000_0001_0000,
+            0b1001_0000_0010_1001,
+            0b1001_0000_0001_0000
+        ]
+    );
+    // Standard comps are encoded as usual alongside them
+    assert_eq!(
+        words("D=D+A\n", &config),
+        words("D=D+A\n", &Config::default())
+    );
+}
+
+#[test]
+fn shift_extension() {
+    let config = config("extensions = shift\n");
+    assert_eq!(
+        words("D=D<<\nAM=M>>;JGT\n", &config),
+        [0b1010_1100_0001_0000, 0b1011_0000_0010_1001]
+    );
+}
+
+#[test]
+fn extended_comp_without_the_extension() {
+    let errors = assemble_with("D=D<<\n", "test.asm", Parser::new()).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].message, "unknown comp 'D<<'");
+    assert_eq!((errors[0].column, errors[0].width), (3, 3));
+    assert_eq!(
+        errors[0].notes,
+        ["'D<<' is part of the shift extension; enable it with 'extensions = shift' in the machine config"]
+    );
+
+    let errors = assemble_with("D=D*A\n", "test.asm", Parser::new()).unwrap_err();
+    assert_eq!(errors[0].message, "unknown comp 'D*A'");
+    assert!(errors[0].notes.is_empty());
+}
+
+#[test]
+fn clashes_with_standard_comps_are_rejected() {
+    let mut set = InstructionSet::new();
+    assert_eq!(
+        set.add_comp("D+A", 0b10_0000_0000),
+        Err(String::from("'D+A' is a standard comp"))
+    );
+    // The encoding of `D+A`
+    assert_eq!(
+        set.add_comp("D*A", 0b11_1000_0010),
+        Err(String::from(
+            "encoding 0b1110000010 of 'D*A' is already used by 'D+A'"
+        ))
+    );
+    // The encoding of `M`, which sets the a-bit
+    assert_eq!(
+        set.add_comp("D*A", 0b11_1111_0000),
+        Err(String::from(
+            "encoding 0b1111110000 of 'D*A' is already used by 'M'"
+        ))
+    );
+    assert!(set.get_comps().is_empty());
+}
+
+#[test]
+fn clashes_between_extended_comps_are_rejected() {
+    let mut set = InstructionSet::new();
+    set.add_extension("shift").unwrap();
+    assert_eq!(
+        set.add_comp("D*A", 0b10_1011_0000),
+        Err(String::from(
+            "encoding 0b1010110000 of 'D*A' is already used by 'D<<'"
+        ))
+    );
+    assert_eq!(
+        set.add_comp("D<<", 0b10_0000_0000),
+        Err(String::from("'D<<' is already defined"))
+    );
+    assert_eq!(
+        set.add_extension("shift"),
+        Err(String::from("'A<<' is already defined"))
+    );
+    assert_eq!(
+        set.add_extension("float"),
+        Err(String::from("unknown extension 'float'"))
+    );
+}
+
+#[test]
+fn invalid_comps_are_rejected() {
+    let mut set = InstructionSet::new();
+    assert_eq!(
+        set.add_comp("D*X", 0b10_0000_0000),
+        Err(String::from("invalid comp mnemonic 'D*X'"))
+    );
+    assert_eq!(
+        set.add_comp("D*A", 0b01_0000_0000),
+        Err(String::from(
+            "encoding of 'D*A' must be 10 bits starting with 1, got 0b100000000"
+        ))
+    );
+}
+
+#[test]
+fn config_errors_point_at_the_line() {
+    let errors =
+        Config::parse("[instructions]\nD+A = 0b1000000000\nD*A = lots\n", "m.cfg").unwrap_err();
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(errors[0].message, "'D+A' is a standard comp");
+    assert_eq!(errors[0].line, 2);
+    assert_eq!(errors[1].message, "invalid encoding 'lots'");
+    assert_eq!(errors[1].line, 3);
+}
+
+#[test]
+fn disassembler_decodes_extended_words() {
+    let config = config(MULTIPLIER);
+    let words = words("D=D*A\nAM=D*M;JGT\n", &config);
+
+    // Without the instruction set, the words are not legal instructions
+    let standard = Disassembler::new().disassemble(&words);
+    assert_eq!(standard.illegal, [0, 1]);
+
+    let disassembler = Disassembler::new().with_instruction_set(&config.instruction_set);
+    assert_eq!(disassembler.decode(words[0]).unwrap().to_string(), "D=D*A");
+    let disassembly = disassembler.disassemble(&words);
+    assert!(disassembly.illegal.is_empty());
+    assert_eq!(disassembly.source, "D=D*A\nAM=D*M;JGT\n");
+}
@@ -0,0 +1,95 @@
+//! Parsing and writing the JSON used by source maps and the language server.
+
+use hack_assembler::json::{self, Value};
+
+fn parse(text: &str) -> Value {
+    match Value::parse(text) {
+        Ok(value) => value,
+        Err(error) => panic!("failed to parse {:?}: {}", text, error),
+    }
+}
+
+#[test]
+fn documents() {
+    let value = parse(r#" {"name": "Sum", "lines": [1, 2, null], "ok": true, "empty": {}} "#);
+    assert_eq!(value.get("name").and_then(Value::as_str), Some("Sum"));
+    assert_eq!(
+        value.get("lines"),
+        Some(&Value::Array(vec![
+            Value::Number(1.0),
+            Value::Number(2.0),
+            Value::Null
+        ]))
+    );
+    assert_eq!(value.get("ok"), Some(&Value::Bool(true)));
+    assert_eq!(value.get("empty"), Some(&Value::Object(Vec::new())));
+    assert_eq!(value.get("missing"), None);
+    assert_eq!(parse("[ ]"), Value::Array(Vec::new()));
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(
+        parse(r#""\"\\\/\b\f\n\r\t""#),
+        Value::from("\"\\/\u{8}\u{c}\n\r\t")
+    );
+    assert_eq!(parse(r#""\u0041\u00e9\u4E2D""#), Value::from("Aé中"));
+    assert_eq!(parse("\"día\""), Value::from("día"));
+    assert!(Value::parse(r#""\x""#).is_err());
+    assert!(Value::parse(r#""\u12""#).is_err());
+    assert!(Value::parse(r#""open"#).is_err());
+}
+
+#[test]
+fn surrogate_pairs() {
+    assert_eq!(parse(r#""\ud83d\ude00""#), Value::from("\u{1f600}"));
+    // Unpaired halves become the replacement character
+    assert_eq!(parse(r#""\ud83dx""#), Value::from("\u{fffd}x"));
+    assert_eq!(parse(r#""\ud83d""#), Value::from("\u{fffd}"));
+    assert_eq!(parse(r#""\ude00\ud83d""#), Value::from("\u{fffd}\u{fffd}"));
+    // The escape after an unpaired high half is still read
+    assert_eq!(parse(r#""\ud83d\n""#), Value::from("\u{fffd}\n"));
+    assert_eq!(parse(r#""\ud83d\u0041""#), Value::from("\u{fffd}A"));
+}
+
+#[test]
+fn numbers() {
+    assert_eq!(parse("0"), Value::Number(0.0));
+    assert_eq!(parse("-12"), Value::Number(-12.0));
+    assert_eq!(parse("2.5e3"), Value::Number(2500.0));
+    assert_eq!(parse("1E-2"), Value::Number(0.01));
+    assert_eq!(parse("42").as_usize(), Some(42));
+    assert_eq!(parse("-1").as_usize(), None);
+    assert_eq!(parse("1.5").as_usize(), None);
+    assert!(Value::parse("1-2").is_err());
+    assert!(Value::parse("-").is_err());
+}
+
+#[test]
+fn trailing_data() {
+    assert_eq!(
+        Value::parse("{} x"),
+        Err(String::from("unexpected data at byte 3"))
+    );
+    assert!(Value::parse("[1] [2]").is_err());
+    assert!(Value::parse("[1,]").is_err());
+    assert!(Value::parse("{\"a\" 1}").is_err());
+    assert!(Value::parse("").is_err());
+}
+
+#[test]
+fn writing_round_trips() {
+    let value = Value::object(vec![
+        ("file", Value::from("a \"b\"\n\u{1}")),
+        ("line", Value::from(3)),
+        ("ok", Value::from(false)),
+        ("none", Value::Null),
+    ]);
+    let text = value.to_string();
+    assert_eq!(
+        text,
+        r#"{"file":"a \"b\"\n\u0001","line":3,"ok":false,"none":null}"#
+    );
+    assert_eq!(parse(&text), value);
+    assert_eq!(json::string("tab\there"), r#""tab\there""#);
+}
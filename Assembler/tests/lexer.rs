@@ -0,0 +1,86 @@
+//! Tokens and where they come from.
+
+use std::ops::Range;
+
+use hack_assembler::assemble_str;
+use hack_assembler::lexer::{AValue, Comp, Instruction, Lexer};
+
+/// Where `text` lies in memory.
+fn span(text: &str) -> Range<usize> {
+    let start = text.as_ptr() as usize;
+    start..start + text.len()
+}
+
+#[test]
+fn tokens_borrow_from_the_input() {
+    let code = String::from("(LOOP)\n    @counter // count\nM=M+1\n@LOOP\n0;JMP\n");
+    let lexer = Lexer::new(&code, "test.asm").unwrap();
+    let input = span(&code);
+    for token in lexer.get_tokens() {
+        let source = span(token.source());
+        assert!(
+            input.start <= source.start && source.end <= input.end,
+            "{:?} was copied",
+            token.source()
+        );
+        let name = match token.get_token() {
+            Instruction::LInstruction(label) => label,
+            Instruction::AInstruction(AValue::Symbol(symbol)) => symbol,
+            _ => continue,
+        };
+        let name = span(name);
+        assert!(input.start <= name.start && name.end <= input.end);
+    }
+}
+
+#[test]
+fn expansions_are_copied() {
+    let code = ".macro INC name\n    @\\name\n    M=M+1\n.endm\n    INC counter\n";
+    let lexer = Lexer::new(code, "test.asm").unwrap();
+    let tokens = lexer.get_tokens();
+    // The call, then the two lines it expands to
+    assert_eq!(tokens.len(), 3, "{:?}", tokens);
+    assert_eq!(tokens[1].source(), "    @counter");
+    assert!(!span(code).contains(&span(tokens[1].source()).start));
+    assert_eq!(
+        tokens[1].get_token(),
+        &Instruction::AInstruction(AValue::Symbol("counter".into()))
+    );
+    assert_eq!(tokens[1].line(), 2);
+}
+
+#[test]
+fn commutative_comps_are_the_same_instruction() {
+    let pairs = [
+        ("D+1", "1+D"),
+        ("A+1", "1+A"),
+        ("M+1", "1+M"),
+        ("D+A", "A+D"),
+        ("D+M", "M+D"),
+        ("D&A", "A&D"),
+        ("D&M", "M&D"),
+        ("D|A", "A|D"),
+        ("D|M", "M|D"),
+    ];
+    for (canonical, swapped) in pairs {
+        let comp = Comp::from_mnemonic(canonical).unwrap();
+        assert_eq!(Comp::from_mnemonic(swapped), Some(comp));
+        assert_eq!(comp.mnemonic(), canonical);
+        let words = |comp| {
+            assemble_str(&format!("AM={};JGT\n", comp), "test.asm")
+                .unwrap()
+                .words
+        };
+        assert_eq!(words(swapped), words(canonical), "{}", swapped);
+    }
+    // Subtraction is not commutative
+    assert_ne!(Comp::from_mnemonic("A-D"), Comp::from_mnemonic("D-A"));
+    assert_eq!(Comp::from_mnemonic("1-D"), None);
+}
+
+#[test]
+fn standard_comps_round_trip() {
+    for comp in Comp::STANDARD {
+        assert_eq!(Comp::from_mnemonic(comp.mnemonic()), Some(comp));
+    }
+}
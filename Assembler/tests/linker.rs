@@ -0,0 +1,193 @@
+//! Object files and linking modules with `.global`, `.extern` and variables.
+
+use hack_assembler::assemble_object;
+use hack_assembler::config::Config;
+use hack_assembler::linker::{link, Linked};
+use hack_assembler::object::{Object, Relocation, RelocationKind};
+use hack_assembler::parser::Parser;
+use hack_assembler::symbols::SymbolKind;
+use hack_assembler::{assemble_str, error::AssembleError};
+
+const MAIN: &str = "\
+.extern Math.double
+    @21
+    D=A
+    @x
+    M=D
+    @RETURN
+    D=A
+    @Math.double
+    0;JMP
+(RETURN)
+    @count
+    M=M+1
+(END)
+    @END
+    0;JMP
+";
+
+const MATH: &str = "\
+.global Math.double
+(Math.double)
+    @R13
+    M=D
+    @x
+    D=M
+    M=D+M
+    @R13
+    A=M
+    0;JMP
+";
+
+fn object(code: &str, file: &str) -> Object {
+    match assemble_object(code, file, Parser::new()) {
+        Ok((object, _)) => object,
+        Err(errors) => panic!("failed to assemble {}: {:?}", file, errors),
+    }
+}
+
+fn link_modules(modules: &[(&str, &str)]) -> Result<Linked, Vec<AssembleError>> {
+    let objects: Vec<_> = modules
+        .iter()
+        .map(|(file, code)| (file.to_string(), object(code, file)))
+        .collect();
+    link(&objects, &Config::default())
+}
+
+#[test]
+fn object_round_trip() {
+    let object = object(MAIN, "main.asm");
+    assert_eq!(object.imports, ["Math.double"]);
+    assert_eq!(object.variables, ["x", "count"]);
+    assert!(object.relocations.contains(&Relocation {
+        offset: 6,
+        kind: RelocationKind::Import(String::from("Math.double")),
+    }));
+    assert!(object.relocations.contains(&Relocation {
+        offset: 4,
+        kind: RelocationKind::Label,
+    }));
+
+    let mut text = Vec::new();
+    object.write(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("HACKOBJ 1\n"), "{}", text);
+    assert_eq!(Object::read(&text, "main.hobj").unwrap(), object);
+
+    let exported = self::object(MATH, "math.asm");
+    assert_eq!(exported.exports, [(String::from("Math.double"), 0)]);
+    let mut text = Vec::new();
+    exported.write(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(Object::read(&text, "math.hobj").unwrap(), exported);
+}
+
+#[test]
+fn invalid_object_is_rejected() {
+    assert!(Object::read("HACKOBJ 2\ncode 0\n", "bad.hobj").is_err());
+    assert!(Object::read("HACKOBJ 1\ncode 2\n0000\n", "bad.hobj").is_err());
+    assert!(Object::read("HACKOBJ 1\ncode 1\nXYZW\n", "bad.hobj").is_err());
+}
+
+#[test]
+fn export_past_the_code_is_rejected() {
+    // A label right after the last word is fine
+    let object = Object::read("HACKOBJ 1\nexport END 1\ncode 1\n0000\n", "end.hobj").unwrap();
+    assert_eq!(object.exports, [(String::from("END"), 1)]);
+
+    let errors = Object::read("HACKOBJ 1\nexport END 2\ncode 1\n0000\n", "bad.hobj").unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        "export 'END' at offset 2 is past the end of the 1 words of code"
+    );
+    assert_eq!(errors[0].line, 2);
+}
+
+#[test]
+fn exported_address_is_range_checked() {
+    let module = |exports: Vec<(String, u16)>, size: usize| Object {
+        exports,
+        words: vec![0; size],
+        ..Object::default()
+    };
+    let config = Config {
+        rom_size: usize::MAX,
+        ..Config::default()
+    };
+
+    let modules = [(
+        String::from("a.hobj"),
+        module(vec![(String::from("A"), 9)], 4),
+    )];
+    let errors = link(&modules, &config).unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "exported label 'A' at offset 9 is past the end of the module"
+    );
+
+    // 0x10000 would wrap to 0 in 16 bits
+    let modules = [
+        (String::from("a.hobj"), module(Vec::new(), 0xffff)),
+        (
+            String::from("b.hobj"),
+            module(vec![(String::from("B"), 1)], 1),
+        ),
+    ];
+    let errors = link(&modules, &config).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        "exported label 'B' is at 65536, past the end of the address space"
+    );
+    assert_eq!(errors[0].file, "b.hobj");
+}
+
+#[test]
+fn two_modules_link_like_one_program() {
+    let linked = link_modules(&[("main.asm", MAIN), ("math.asm", MATH)]).unwrap();
+    assert!(linked.warnings.is_empty(), "{:?}", linked.warnings);
+
+    // The same program assembled as a single file
+    let whole = format!("{}{}", MAIN.replace(".extern Math.double\n", ""), MATH);
+    let whole = whole.replace(".global Math.double\n", "");
+    let assembly = assemble_str(&whole, "whole.asm").unwrap();
+    assert_eq!(linked.words, assembly.words);
+
+    assert_eq!(linked.symbols["Math.double"].address, 12);
+    assert_eq!(linked.symbols["Math.double"].kind, SymbolKind::Label);
+    // Both modules share `x`
+    assert_eq!(linked.symbols["x"].address, 16);
+    assert_eq!(linked.symbols["count"].address, 17);
+}
+
+#[test]
+fn unresolved_import_is_an_error() {
+    let errors = link_modules(&[("main.asm", MAIN)]).unwrap_err();
+    assert!(errors
+        .iter()
+        .any(|error| error.message == "unresolved symbol 'Math.double': no module exports it"));
+}
+
+#[test]
+fn variable_named_like_an_exported_label_is_an_error() {
+    // `.extern` forgotten: `Math.double` would silently become the label
+    let main = MAIN.replace(".extern Math.double\n", "");
+    let errors = link_modules(&[("main.asm", &main), ("math.asm", MATH)]).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        "variable 'Math.double' has the name of a label exported by math.asm"
+    );
+    assert_eq!(errors[0].file, "main.asm");
+    assert_eq!(
+        errors[0].notes,
+        ["declare it with '.extern Math.double' to use the label"]
+    );
+}
+
+#[test]
+fn label_exported_twice_is_an_error() {
+    let errors = link_modules(&[("math.asm", MATH), ("copy.asm", MATH)]).unwrap_err();
+    assert_eq!(errors[0].message, "duplicate symbol 'Math.double'");
+}
@@ -0,0 +1,130 @@
+//! Each lint rule, and the code this repository's own VM translator emits
+//! linting clean.
+
+use hack_assembler::error::AssembleError;
+use hack_assembler::lint::Options;
+use hack_assembler::lint_with;
+use hack_assembler::parser::Parser;
+
+/// `tests/data/Sum.vm` translated by `vm`, which pops the stack with
+/// `@SP / AM=M-1 / D=M`.
+const VM_OUTPUT: &str = include_str!("data/Sum.asm");
+
+const STRICT: Options = Options { strict: true };
+
+fn warnings(code: &str, options: Options) -> Vec<AssembleError> {
+    match lint_with(code, "test.asm", Parser::new(), options) {
+        Ok(assembly) => assembly.warnings,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+/// The single warning linting `code` gives.
+fn warning(code: &str, options: Options) -> AssembleError {
+    let mut warnings = warnings(code, options);
+    assert_eq!(warnings.len(), 1, "{:#?}", warnings);
+    let warning = warnings.remove(0);
+    assert!(warning.is_warning());
+    warning
+}
+
+#[test]
+fn unused_label() {
+    let warning = warning("@R0\nD=M\n  (UNUSED)\n@R1\nM=D\n", Options::default());
+    assert_eq!(warning.message, "label 'UNUSED' is never used");
+    assert_eq!(
+        (warning.line, warning.column, warning.width),
+        (3, 3, "(UNUSED)".len())
+    );
+}
+
+#[test]
+fn exported_label_is_used() {
+    let code = ".global start\n(start)\n@R0\nM=0\n";
+    assert!(warnings(code, Options::default()).is_empty());
+}
+
+#[test]
+fn misspelt_label_becomes_a_variable() {
+    let code = "(LOOP)\n@R0\nM=M+1\n@loop\nM=0\n@LOOP\n0;JMP\n";
+    let warning = warning(code, Options::default());
+    assert_eq!(
+        warning.message,
+        "'loop' is allocated as a variable, but looks like a misspelling of label 'LOOP'"
+    );
+    // The name after the `@`
+    assert_eq!((warning.line, warning.column, warning.width), (4, 2, 4));
+}
+
+#[test]
+fn misspelt_predefined_symbol() {
+    let warning = warning("@SCRENN\nM=-1\n", Options::default());
+    assert_eq!(
+        warning.message,
+        "'SCRENN' is allocated as a variable, but looks like a misspelling of predefined symbol 'SCREEN'"
+    );
+    assert_eq!((warning.line, warning.column, warning.width), (1, 2, 6));
+}
+
+#[test]
+fn unrelated_variables_are_not_reported() {
+    let code = "(LOOP)\n@counter\nM=M+1\n@i\nM=0\n@LOOP\n0;JMP\n";
+    assert!(warnings(code, Options::default()).is_empty());
+}
+
+#[test]
+fn jump_without_loading_a() {
+    let code = "@R0\nD=M\nD;JGT\n";
+    let warning = warning(code, Options::default());
+    assert_eq!(
+        warning.message,
+        "'D;JGT' jumps to the address in A, but the previous instruction did not load A"
+    );
+    assert_eq!((warning.line, warning.column, warning.width), (3, 1, 5));
+
+    // Writing A counts as loading it
+    assert!(warnings("@R0\nA=M\n0;JMP\n", Options::default()).is_empty());
+}
+
+#[test]
+fn unreachable_after_jmp() {
+    let code = "(END)\n@END\n0;JMP\n    @R0\nM=0\n";
+    let warning = warning(code, Options::default());
+    assert_eq!(
+        warning.message,
+        "unreachable instruction after an unconditional jump"
+    );
+    assert_eq!((warning.line, warning.column, warning.width), (4, 5, 3));
+
+    // A label makes the code after it reachable
+    let code = "(END)\n@END\n0;JMP\n(MORE)\n@MORE\n0;JMP\n";
+    assert!(warnings(code, Options::default()).is_empty());
+}
+
+#[test]
+fn writing_a_and_reading_m_is_reported_when_strict() {
+    let code = "@SP\nAM=M-1\nD=M\n";
+    assert!(warnings(code, Options::default()).is_empty());
+    let warning = warning(code, STRICT);
+    assert_eq!(
+        warning.message,
+        "'AM=M-1' writes A and reads M; M refers to the address in A before the write"
+    );
+    assert_eq!((warning.line, warning.column, warning.width), (2, 1, 6));
+
+    // The pointer dereference is the usual idiom, even when strict
+    assert!(warnings("@SP\nA=M\nD=M\n", STRICT).is_empty());
+    assert!(warnings("@SP\nAM=D+1\n", STRICT).is_empty());
+}
+
+#[test]
+fn vm_translator_output_lints_clean() {
+    let assembly = lint_with(VM_OUTPUT, "Sum.asm", Parser::new(), Options::default()).unwrap();
+    assert!(assembly.warnings.is_empty(), "{:#?}", assembly.warnings);
+}
+
+#[test]
+fn stack_pop_idiom_is_not_reported() {
+    let code = "@SP\nAM=M-1\nD=M\nA=A-1\nM=D+M\n";
+    assert!(warnings(code, Options::default()).is_empty());
+}
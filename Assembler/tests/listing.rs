@@ -0,0 +1,54 @@
+//! The listing written by `--listing`.
+
+use hack_assembler::assemble_str;
+use hack_assembler::listing::write_listing;
+use hack_assembler::Assembly;
+
+const PROGRAM: &str = "\
+// Counts up forever
+.equ START 2
+    @START
+    D=A
+(LOOP)
+    @i
+    M=D+M   // add
+    @LOOP
+    0;JMP
+";
+
+fn listing(assembly: &Assembly) -> String {
+    let mut output = Vec::new();
+    write_listing(assembly, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn addresses_words_and_source() {
+    let assembly = assemble_str(PROGRAM, "count.asm").unwrap();
+    assert_eq!(
+        listing(&assembly),
+        "  ROM  Binary            Hex    Line  Source
+                                      // count.asm
+                                   2  .equ START 2
+00000  0000000000000010  0002      3      @START
+00001  1110110000010000  EC10      4      D=A
+00002                              5  (LOOP)
+00002  0000000000010000  0010      6      @i
+00003  1111000010001000  F088      7      M=D+M   // add
+00004  0000000000000010  0002      8      @LOOP
+00005  1110101010000111  EA87      9      0;JMP
+"
+    );
+}
+
+#[test]
+fn label_missing_from_the_symbol_table() {
+    let mut assembly = assemble_str(PROGRAM, "count.asm").unwrap();
+    assembly.symbols.remove("LOOP");
+    let listing = listing(&assembly);
+    assert!(
+        listing.contains("\n                                   5  (LOOP)\n"),
+        "{}",
+        listing
+    );
+}
@@ -0,0 +1,169 @@
+//! Macro definitions, calls and expansion errors.
+
+use hack_assembler::error::AssembleError;
+use hack_assembler::{assemble_str, expand_str};
+
+const COPY: &str = "\
+.macro COPY src, dst
+    @\\src
+    D=M
+    @\\dst
+    M=D
+.endm
+";
+
+const WAIT: &str = "\
+.macro WAIT key
+(LOOP)
+    @KBD
+    D=M
+    @\\key
+    D=D-A
+    @LOOP
+    D;JNE
+.endm
+";
+
+const PUSH: &str = "\
+.macro PUSH_D
+    @SP
+    A=M
+    M=D
+    @SP
+    M=M+1
+.endm
+.macro PUSH value
+    @\\value
+    D=A
+    PUSH_D
+.endm
+";
+
+fn words(code: &str) -> Vec<u16> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly.words,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+fn errors(code: &str) -> Vec<AssembleError> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => panic!("assembled {:?} into {:?}", code, assembly.words),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn parameters_are_substituted() {
+    let code = format!("{}    COPY R0, R1\n    COPY counter,R2\n", COPY);
+    assert_eq!(
+        words(&code),
+        words("@R0\nD=M\n@R1\nM=D\n@counter\nD=M\n@R2\nM=D\n")
+    );
+}
+
+#[test]
+fn parameter_prefixes_are_not_confused() {
+    let code = ".macro SET a, ab\n    @\\ab\n    M=\\a\n.endm\n    SET 1, R5\n";
+    assert_eq!(words(code), words("@R5\nM=1\n"));
+}
+
+#[test]
+fn labels_are_local_to_each_expansion() {
+    let code = format!("{}    WAIT 65\n    WAIT 66\n", WAIT);
+    let assembly = assemble_str(&code, "test.asm").unwrap();
+    assert_eq!(assembly.symbols["WAIT$1$LOOP"].address, 0);
+    assert_eq!(assembly.symbols["WAIT$2$LOOP"].address, 6);
+    assert!(!assembly.symbols.contains_key("LOOP"));
+    assert_eq!(
+        assembly.words,
+        words("(A)\n@KBD\nD=M\n@65\nD=D-A\n@A\nD;JNE\n(B)\n@KBD\nD=M\n@66\nD=D-A\n@B\nD;JNE\n")
+    );
+}
+
+#[test]
+fn local_labels_do_not_clash_with_program_labels() {
+    let code = format!("{}(LOOP)\n    WAIT 65\n    @LOOP\n    0;JMP\n", WAIT);
+    let assembly = assemble_str(&code, "test.asm").unwrap();
+    assert_eq!(assembly.symbols["LOOP"].address, 0);
+    assert_eq!(assembly.words[6], 0);
+}
+
+#[test]
+fn nested_calls() {
+    let code = format!("{}    PUSH 7\n    PUSH 8\n", PUSH);
+    let push = |value| format!("@{}\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n", value);
+    assert_eq!(words(&code), words(&(push(7) + &push(8))));
+
+    let expanded = expand_str(&code, "test.asm").unwrap();
+    assert!(
+        expanded.contains("// PUSH 7\n    @7\n    D=A\n// PUSH_D\n    @SP\n"),
+        "{}",
+        expanded
+    );
+}
+
+#[test]
+fn unterminated_macro() {
+    let errors = errors(".macro LOOP_FOREVER\n(TOP)\n    @TOP\n    0;JMP\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        "macro 'LOOP_FOREVER' has no matching '.endm'"
+    );
+    assert_eq!(errors[0].line, 1);
+}
+
+#[test]
+fn endm_without_macro() {
+    let errors = errors("    D=0\n.endm\n");
+    assert_eq!(errors[0].message, "'.endm' without a matching '.macro'");
+}
+
+#[test]
+fn wrong_argument_count() {
+    let errors = errors(&format!("{}    COPY R0\n", COPY));
+    assert_eq!(
+        errors[0].message,
+        "macro 'COPY' takes 2 argument(s) but 1 were given"
+    );
+    assert_eq!(errors[0].line, 7);
+
+    let errors = self::errors(&format!("{}    COPY R0, R1, R2\n", COPY));
+    assert_eq!(
+        errors[0].message,
+        "macro 'COPY' takes 2 argument(s) but 3 were given"
+    );
+    let errors = self::errors(&format!("{}    COPY R0,\n", COPY));
+    assert_eq!(errors[0].message, "empty macro argument");
+}
+
+#[test]
+fn errors_in_an_expansion_note_the_call() {
+    let code = format!("{}    COPY R0, R1\n    COPY 1+, R1\n", COPY);
+    let errors = errors(&code);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    // The error points at the body line and notes the call
+    assert_eq!(errors[0].line, 2);
+    assert_eq!(
+        errors[0].notes,
+        ["in expansion of macro 'COPY' at test.asm:8:5"]
+    );
+}
+
+#[test]
+fn recursive_macro() {
+    let errors = errors(".macro FOREVER\n    FOREVER\n.endm\n    FOREVER\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(
+        errors[0].message.ends_with("is 'FOREVER' recursive?"),
+        "{}",
+        errors[0].message
+    );
+}
+
+#[test]
+fn macro_defined_twice() {
+    let errors = errors(&format!("{}{}", COPY, COPY));
+    assert_eq!(errors[0].message, "macro 'COPY' is already defined");
+}
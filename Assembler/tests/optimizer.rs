@@ -0,0 +1,235 @@
+//! Each rule of the peephole optimiser, the cases that must block it, and
+//! optimised programs running the same as the originals.
+
+use hack_assembler::parser::Parser;
+use hack_assembler::symbols::SymbolKind;
+use hack_assembler::{assemble_optimized, assemble_str, Assembly};
+
+fn assemble(code: &str) -> Assembly<'_> {
+    match assemble_str(code, "test.asm") {
+        Ok(assembly) => assembly,
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+fn assemble_optimizing(code: &str) -> Assembly<'_> {
+    match assemble_optimized(code, "test.asm", Parser::new()) {
+        Ok((assembly, saved)) => {
+            assert_eq!(saved, words(code).len() - assembly.words.len());
+            assembly
+        }
+        Err(errors) => panic!("failed to assemble {:?}: {:?}", code, errors),
+    }
+}
+
+fn words(code: &str) -> Vec<u16> {
+    assemble(code).words
+}
+
+fn optimized(code: &str) -> Vec<u16> {
+    assemble_optimizing(code).words
+}
+
+/// Asserts that optimising `before` gives the words of `after`.
+fn assert_optimizes(before: &str, after: &str) {
+    assert_eq!(optimized(before), words(after), "optimising {:?}", before);
+}
+
+fn assert_unchanged(code: &str) {
+    assert_optimizes(code, code);
+}
+
+#[test]
+fn increment_then_decrement_cancel() {
+    assert_optimizes("@SP\nM=M+1\nM=M-1\n@R13\nM=D\n", "@R13\nM=D\n");
+    assert_optimizes("@SP\nM=M-1\nM=M+1\n@R13\nM=D\n", "@R13\nM=D\n");
+}
+
+#[test]
+fn push_then_pop_cancel() {
+    assert_optimizes(
+        "@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nM=M-1\n@SP\nA=M\nD=M\n@R13\nM=D\n",
+        "@SP\nA=M\nM=D\n@SP\nA=M\nD=M\n@R13\nM=D\n",
+    );
+}
+
+#[test]
+fn label_blocks_cancelled_adjustments() {
+    assert_unchanged("@SP\nM=M+1\n(BACK)\nM=M-1\n@BACK\nD;JGT\n");
+}
+
+#[test]
+fn repeated_adjustments_are_kept() {
+    assert_unchanged("@SP\nM=M+1\nM=M+1\n");
+}
+
+#[test]
+fn reload_of_the_same_address() {
+    assert_optimizes("@SP\nM=M-1\n@SP\nA=M\nD=M\n", "@SP\nM=M-1\nA=M\nD=M\n");
+}
+
+#[test]
+fn reload_of_the_same_pointer() {
+    assert_optimizes("@SP\nA=M\nD=M\n@SP\nA=M\nM=D+1\n", "@SP\nA=M\nD=M\nM=D+1\n");
+}
+
+#[test]
+fn pointer_is_forgotten_after_writing_memory() {
+    assert_unchanged("@SP\nA=M\nM=D\n@SP\nA=M\nD=M\n");
+}
+
+#[test]
+fn label_blocks_redundant_loads() {
+    assert_unchanged("@SP\nM=M-1\n(AGAIN)\n@SP\nA=M\nD=M\n@AGAIN\nD;JGT\n");
+}
+
+#[test]
+fn overwritten_load_is_removed() {
+    assert_optimizes("@5\n@7\nD=A\n", "@7\nD=A\n");
+}
+
+#[test]
+fn overwritten_d_is_removed() {
+    assert_optimizes("@3\nD=A\n@4\nD=A\n@R0\nM=D\n", "@4\nD=A\n@R0\nM=D\n");
+}
+
+#[test]
+fn d_read_by_a_jump_is_kept() {
+    assert_unchanged("@3\nD=A\n@END\nD;JGT\n@4\nD=A\n(END)\n@END\n0;JMP\n");
+}
+
+#[test]
+fn load_used_by_a_jump_is_kept() {
+    assert_unchanged("@5\nD=A\n(LOOP)\nD=D-1\n@LOOP\nD;JGT\n");
+}
+
+#[test]
+fn jump_to_the_next_instruction() {
+    assert_optimizes("@NEXT\n0;JMP\n(NEXT)\n@R0\nD=M\n", "(NEXT)\n@R0\nD=M\n");
+    assert_optimizes(
+        "@R1\nD=M\n@NEXT\nD;JGT\n(NEXT)\n@R0\nM=D\n",
+        "@R1\nD=M\n(NEXT)\n@R0\nM=D\n",
+    );
+}
+
+#[test]
+fn jump_over_code_is_kept() {
+    assert_unchanged("@SKIP\n0;JMP\n@R0\nM=0\n(SKIP)\n@R1\nM=0\n");
+}
+
+#[test]
+fn jump_to_the_next_instruction_using_a_is_kept() {
+    // A still holds the address of NEXT when `D=M` reads memory
+    assert_unchanged("@NEXT\n0;JMP\n(NEXT)\nD=M\n");
+}
+
+#[test]
+fn labels_after_removed_code_move_with_it() {
+    let before = "@SP\nM=M-1\n@SP\nA=M\n(BODY)\nD=D-1\n@BODY\nD;JGT\n";
+    let after = "@SP\nM=M-1\nA=M\n(BODY)\nD=D-1\n@BODY\nD;JGT\n";
+    assert_optimizes(before, after);
+    let (assembly, _) = assemble_optimized(before, "test.asm", Parser::new()).unwrap();
+    assert_eq!(assembly.symbols["BODY"].address, 3);
+}
+
+#[test]
+fn program_asm_runs_the_same() {
+    assert_runs_the_same(include_str!("../Program.asm"));
+}
+
+#[test]
+fn vm_translator_output_runs_the_same() {
+    let code = include_str!("data/Sum.asm");
+    // Sum.vm adds 5 + 4 + 3 + 2 + 1 into `static 1`
+    assert_eq!(run(&words(code))[17], 15);
+    assert_runs_the_same(code);
+}
+
+/// Asserts that `code` saves words when optimised and leaves RAM as the
+/// original does. Only code addresses, such as the return address a VM
+/// `call` pushes, may differ, and only by the label moving.
+fn assert_runs_the_same(code: &str) {
+    let original = assemble(code);
+    let optimized = assemble_optimizing(code);
+    assert!(optimized.words.len() < original.words.len());
+
+    let moved = |before: u16, after: u16| {
+        original.symbols.iter().any(|(name, symbol)| {
+            symbol.kind == SymbolKind::Label
+                && symbol.address == before
+                && optimized.symbols[name].address == after
+        })
+    };
+    let (before, after) = (run(&original.words), run(&optimized.words));
+    let differences: Vec<_> = (0..before.len())
+        .filter(|address| {
+            !(before[*address] == after[*address] || moved(before[*address], after[*address]))
+        })
+        .map(|address| (address, before[address], after[address]))
+        .collect();
+    assert!(
+        differences.is_empty(),
+        "(address, original, optimised): {:?}",
+        differences
+    );
+}
+
+/// Runs `words` on a Hack CPU with SP at 256, returning the RAM once the
+/// program runs past its end or jumps to the instruction before the jump,
+/// as `(END) / @END / 0;JMP` does.
+fn run(words: &[u16]) -> Vec<u16> {
+    let mut ram = vec![0; 0x8000];
+    ram[0] = 256;
+    let (mut a, mut d, mut pc) = (0u16, 0u16, 0usize);
+    for _ in 0..1_000_000 {
+        let word = match words.get(pc) {
+            Some(word) => *word,
+            None => return ram,
+        };
+        if word & 0x8000 == 0 {
+            a = word;
+            pc += 1;
+            continue;
+        }
+        let address = (a & 0x7fff) as usize;
+        let y = if word & 0x1000 != 0 { ram[address] } else { a };
+        let out = alu(d, y, word >> 6 & 0b11_1111);
+        let target = a as usize;
+        if word & 0b00_1000 != 0 {
+            ram[address] = out;
+        }
+        if word & 0b01_0000 != 0 {
+            d = out;
+        }
+        if word & 0b10_0000 != 0 {
+            a = out;
+        }
+        let negative = (out as i16) < 0;
+        let taken = (word & 0b100 != 0 && negative)
+            || (word & 0b010 != 0 && out == 0)
+            || (word & 0b001 != 0 && !negative && out != 0);
+        if !taken {
+            pc += 1;
+        } else if target + 1 == pc {
+            return ram;
+        } else {
+            pc = target;
+        }
+    }
+    panic!("program did not stop");
+}
+
+/// The Hack ALU, with `control` holding the zx, nx, zy, ny, f and no bits.
+fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let bit = |index: u16| control >> (5 - index) & 1 != 0;
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) {
+        !out
+    } else {
+        out
+    }
+}
@@ -0,0 +1,99 @@
+//! The exact bytes of every output format.
+
+use hack_assembler::output::Format;
+
+fn write(format: Format, words: &[u16]) -> Vec<u8> {
+    let mut output = Vec::new();
+    format.write(words, &mut output).unwrap();
+    output
+}
+
+fn text(format: Format, words: &[u16]) -> String {
+    String::from_utf8(write(format, words)).unwrap()
+}
+
+#[test]
+fn names() {
+    for name in Format::NAMES {
+        let format = Format::from_name(name).unwrap();
+        assert!(!format.extension().is_empty());
+    }
+    assert_eq!(Format::from_name("elf"), None);
+    assert_eq!(Format::from_name("ihex").unwrap().extension(), "ihex");
+}
+
+#[test]
+fn hack_and_hex() {
+    let words = [0x0005, 0xfc10, 0x8000];
+    assert_eq!(
+        text(Format::Hack, &words),
+        "0000000000000101\n1111110000010000\n1000000000000000\n"
+    );
+    assert_eq!(text(Format::Hex, &words), "0005\nfc10\n8000\n");
+    assert_eq!(text(Format::Hack, &[]), "");
+}
+
+#[test]
+fn binary_byte_order() {
+    let words = [0x1234, 0xabcd];
+    assert_eq!(write(Format::BinaryLe, &words), [0x34, 0x12, 0xcd, 0xab]);
+    assert_eq!(write(Format::BinaryBe, &words), [0x12, 0x34, 0xab, 0xcd]);
+}
+
+#[test]
+fn intel_hex_records() {
+    // 20 bytes: a full record of 16, then one of 4
+    let words = [
+        0x0002, 0xec10, 0x0003, 0xe308, 0x0000, 0xe088, 0x7fff, 0xffff, 0x1234, 0xabcd,
+    ];
+    assert_eq!(
+        text(Format::IntelHex, &words),
+        "\
+:100000000002EC100003E3080000E0887FFFFFFF20
+:040010001234ABCD2E
+:00000001FF
+"
+    );
+    // Only the end-of-file record
+    assert_eq!(text(Format::IntelHex, &[]), ":00000001FF\n");
+}
+
+#[test]
+fn intel_hex_full_rom() {
+    let words = vec![0xffff; 32768];
+    let text = text(Format::IntelHex, &words);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 4096 + 1);
+    assert_eq!(lines[0], ":10000000FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF00");
+    // The last record starts at 0xfff0, still without an extended address
+    assert_eq!(lines[4095], ":10FFF000FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF11");
+    assert_eq!(lines[4096], ":00000001FF");
+}
+
+#[test]
+fn verilog_memory_files() {
+    let words = [0x0005, 0x8000];
+    assert_eq!(
+        text(Format::ReadMemB, &words),
+        "// Hack ROM, 2 words\n0000000000000101\n1000000000000000\n"
+    );
+    assert_eq!(
+        text(Format::ReadMemH, &words),
+        "// Hack ROM, 2 words\n0005\n8000\n"
+    );
+    assert_eq!(text(Format::ReadMemH, &[]), "// Hack ROM, 0 words\n");
+}
+
+#[test]
+fn logisim_image() {
+    let mut words = vec![1, 1, 1, 2, 2, 2, 2, 0xabcd];
+    words.extend([0; 20]);
+    words.extend(5..=0xd);
+    // Runs of 4 or more are written as `count*value`, with the count in
+    // decimal, and 8 values go on each line
+    assert_eq!(
+        text(Format::Logisim, &words),
+        "v2.0 raw\n1 1 1 4*2 abcd 20*0 5 6\n7 8 9 a b c d\n"
+    );
+    assert_eq!(text(Format::Logisim, &[]), "v2.0 raw\n");
+}
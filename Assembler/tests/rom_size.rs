@@ -0,0 +1,72 @@
+//! Regression tests for programs that do not fit in ROM.
+
+use hack_assembler::assemble_with;
+use hack_assembler::config::Config;
+use hack_assembler::parser::{Parser, ROM_SIZE};
+
+/// `count` instructions with a label after every thousandth one.
+fn program(count: usize) -> String {
+    let mut code = String::new();
+    for index in 0..count {
+        if index % 1000 == 0 {
+            code.push_str(&format!("(L{})\n", index));
+        }
+        code.push_str("D=D+1\n");
+    }
+    code.push_str("(END)\n@END\n0;JMP\n");
+    code
+}
+
+#[test]
+fn oversized_program_is_an_error_not_an_overflow() {
+    let errors = assemble_with(&program(70_000), "big.asm", Parser::new()).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        format!(
+            "program needs 70002 words of ROM but only {} are available",
+            ROM_SIZE
+        )
+    );
+    // Reported at the first instruction past the end of ROM
+    assert_eq!(errors[0].line, ROM_SIZE + ROM_SIZE / 1000 + 2);
+}
+
+#[test]
+fn oversized_program_for_a_smaller_rom() {
+    let parser = Parser::new().with_rom_size(100);
+    let errors = assemble_with(&program(100), "big.asm", parser).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].message,
+        "program needs 102 words of ROM but only 100 are available"
+    );
+}
+
+#[test]
+fn program_filling_rom_exactly() {
+    let code = program(ROM_SIZE - 2);
+    let assembly = assemble_with(&code, "full.asm", Parser::new()).unwrap();
+    assert_eq!(assembly.words.len(), ROM_SIZE);
+    assert_eq!(assembly.symbols["END"].address as usize, ROM_SIZE - 2);
+    assert_eq!(assembly.symbols["L32000"].address, 32000);
+}
+
+#[test]
+fn rom_size_is_clamped_to_the_address_space() {
+    let config = Config {
+        rom_size: usize::MAX,
+        ..Config::default()
+    };
+    let code = "D=D+1\n".repeat(ROM_SIZE + 1);
+    let parser = Parser::new().with_config(config);
+    assert!(assemble_with(&code, "big.asm", parser).is_err());
+}
+
+#[test]
+fn label_past_the_last_address() {
+    let code = format!("{}(END)\n", "D=D+1\n".repeat(ROM_SIZE));
+    let assembly = assemble_with(&code, "full.asm", Parser::new()).unwrap();
+    assert_eq!(assembly.words.len(), ROM_SIZE);
+    assert_eq!(assembly.symbols["END"].address as usize, ROM_SIZE);
+}
@@ -0,0 +1,100 @@
+//! The source map written by `--source-map`: which file, line and column
+//! every ROM address came from, through macro expansions and includes.
+
+use std::env;
+use std::fs;
+use std::process;
+
+use hack_assembler::assemble_str;
+use hack_assembler::json::Value;
+use hack_assembler::sourcemap::write_source_map;
+
+const STACK: &str = "\
+.macro PUSH_D
+    @SP
+    A=M
+    M=D
+    @SP
+    M=M+1
+.endm
+.macro PUSH value
+    @\\value
+    D=A
+    PUSH_D
+.endm
+";
+
+const MAIN: &str = "\
+.include \"stack.asm\"
+  @R0
+  PUSH 7
+(END)
+  @END
+  0;JMP
+";
+
+/// `(source, line, column)` of a word or a macro call.
+fn place(value: &Value) -> (usize, usize, usize) {
+    let field = |name| value.get(name).and_then(Value::as_usize).unwrap();
+    (field("source"), field("line"), field("column"))
+}
+
+#[test]
+fn words_point_into_macros_and_included_files() {
+    let dir = env::temp_dir().join(format!("hack_sourcemap_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("stack.asm"), STACK).unwrap();
+    let main = dir.join("main.asm");
+    let main = main.to_str().unwrap();
+    let assembly = assemble_str(MAIN, main).unwrap();
+
+    let mut output = Vec::new();
+    write_source_map(&assembly, &mut output).unwrap();
+    let map = Value::parse(&String::from_utf8(output).unwrap()).unwrap();
+    assert_eq!(map.get("version").and_then(Value::as_usize), Some(1));
+    let stack = dir.join("stack.asm");
+    assert_eq!(
+        map.get("sources"),
+        Some(&Value::Array(vec![
+            Value::from(main),
+            Value::from(stack.to_str().unwrap())
+        ]))
+    );
+
+    let words = map.get("words").and_then(Value::as_array).unwrap();
+    assert_eq!(words.len(), assembly.words.len());
+    let expected = [
+        // `@R0` in main.asm
+        ((0, 2, 3), vec![]),
+        // `PUSH 7`, through the body of `PUSH` in stack.asm
+        ((1, 9, 5), vec![("PUSH", (0, 3, 3))]),
+        ((1, 10, 5), vec![("PUSH", (0, 3, 3))]),
+        // `PUSH_D`, called from `PUSH`
+        ((1, 2, 5), vec![("PUSH_D", (1, 11, 5)), ("PUSH", (0, 3, 3))]),
+        ((1, 3, 5), vec![("PUSH_D", (1, 11, 5)), ("PUSH", (0, 3, 3))]),
+        ((1, 4, 5), vec![("PUSH_D", (1, 11, 5)), ("PUSH", (0, 3, 3))]),
+        ((1, 5, 5), vec![("PUSH_D", (1, 11, 5)), ("PUSH", (0, 3, 3))]),
+        ((1, 6, 5), vec![("PUSH_D", (1, 11, 5)), ("PUSH", (0, 3, 3))]),
+        // Back in main.asm after the label
+        ((0, 5, 3), vec![]),
+        ((0, 6, 3), vec![]),
+    ];
+    assert_eq!(words.len(), expected.len());
+    for (address, (word, (at, calls))) in words.iter().zip(&expected).enumerate() {
+        assert_eq!(word.get("address").and_then(Value::as_usize), Some(address));
+        assert_eq!(place(word), *at, "word {}", address);
+        let expansions: Vec<(&str, (usize, usize, usize))> = word
+            .get("expansions")
+            .and_then(Value::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .map(|call| {
+                (
+                    call.get("macro").and_then(Value::as_str).unwrap(),
+                    place(call),
+                )
+            })
+            .collect();
+        assert_eq!(expansions, *calls, "word {}", address);
+    }
+}
@@ -0,0 +1,109 @@
+//! The symbol table written by `--symbols`, as text and as JSON.
+
+use std::collections::HashMap;
+
+use hack_assembler::assemble_with;
+use hack_assembler::config::Config;
+use hack_assembler::disassembler::read_symbol_map;
+use hack_assembler::json::Value;
+use hack_assembler::parser::Parser;
+use hack_assembler::symbols::{write_symbols, Symbol, SymbolFormat, SymbolKind};
+
+/// A symbol of every kind: a predefined `LED`, labels, constants, variables
+/// and an array.
+const PROGRAM: &str = "\
+.equ ROWS 3
+.define WIDTH ROWS + 3
+.var grid 6
+(START)
+    @LED
+    M=1
+(END)
+    @count
+    M=0
+    @grid
+    @END
+    0;JMP
+";
+
+fn symbols() -> HashMap<String, Symbol> {
+    let config = Config {
+        standard_symbols: false,
+        symbols: vec![(String::from("LED"), 24577)],
+        ..Config::default()
+    };
+    match assemble_with(PROGRAM, "test.asm", Parser::new().with_config(config)) {
+        Ok(assembly) => assembly.symbols,
+        Err(errors) => panic!("failed to assemble: {:?}", errors),
+    }
+}
+
+fn write(symbols: &HashMap<String, Symbol>, format: SymbolFormat) -> String {
+    let mut output = Vec::new();
+    write_symbols(symbols, format, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn text() {
+    let text = write(&symbols(), SymbolFormat::Text);
+    assert_eq!(
+        text,
+        "\
+predefined LED 24577
+label START 0
+label END 2
+variable grid 16 6
+variable count 22
+constant ROWS 3
+constant WIDTH 6
+"
+    );
+
+    // The disassembler reads labels and variables back
+    let map = read_symbol_map(&text, "test.sym").unwrap();
+    assert_eq!(map.labels[&2], "END");
+    assert_eq!(map.variables[&22], "count");
+}
+
+#[test]
+fn json() {
+    let text = write(&symbols(), SymbolFormat::Json);
+    assert_eq!(
+        text,
+        r#"{
+  "symbols": [
+    {"name":"LED","kind":"predefined","address":24577},
+    {"name":"START","kind":"label","address":0},
+    {"name":"END","kind":"label","address":2},
+    {"name":"grid","kind":"variable","address":16,"size":6},
+    {"name":"count","kind":"variable","address":22},
+    {"name":"ROWS","kind":"constant","address":3},
+    {"name":"WIDTH","kind":"constant","address":6}
+  ]
+}
+"#
+    );
+    assert!(Value::parse(&text).is_ok());
+}
+
+#[test]
+fn json_names_are_escaped() {
+    let mut symbols = HashMap::new();
+    symbols.insert(String::from("a\"b\\c"), Symbol::new(7, SymbolKind::Label));
+    let text = write(&symbols, SymbolFormat::Json);
+    let value = Value::parse(&text).unwrap();
+    let entry = &value.get("symbols").and_then(Value::as_array).unwrap()[0];
+    assert_eq!(entry.get("name").and_then(Value::as_str), Some("a\"b\\c"));
+    assert_eq!(entry.get("address").and_then(Value::as_usize), Some(7));
+}
+
+#[test]
+fn empty_table() {
+    let symbols = HashMap::new();
+    assert_eq!(write(&symbols, SymbolFormat::Text), "");
+    assert_eq!(
+        write(&symbols, SymbolFormat::Json),
+        "{\n  \"symbols\": [\n  ]\n}\n"
+    );
+}